@@ -425,6 +425,7 @@ export const BPIDatasetExplorer = () => {
             <Chart
               data={currentData.map((data) => ({
                 ...data,
+                valueSats: data.valueMsats / 1000,
                 epochTime: getEpochTime(data)
               }))}
             >
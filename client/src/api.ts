@@ -33,7 +33,9 @@ export interface BPISeriesEntry {
   year: number;
   month: number;
   day: number;
-  valueSats: number;
+  valueMsats: number;
+  valueUsd?: number;
+  observed: boolean;
 }
 
 export const getBPIDatasets = async (): Promise<BPISeriesRange[]> => {
@@ -65,4 +67,10 @@ export const getBPIItems = async (): Promise<BPIItem[]> => {
 export interface BPIItem {
   itemCode: string;
   itemName: string;
+  priceUnit: BPIPriceUnit | null;
+}
+
+export interface BPIPriceUnit {
+  quantity: number;
+  unit: string;
 }
\ No newline at end of file
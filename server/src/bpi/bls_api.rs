@@ -0,0 +1,318 @@
+use super::btc_price_provider::build_http_client;
+use super::cpi_ap::SeriesEntry;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_BLS_API_URL: &str = "https://api.bls.gov/publicAPI/v2/timeseries/data/";
+
+/// Maximum number of series IDs that the BLS API accepts in a single request.
+const MAX_SERIES_PER_REQUEST: usize = 50;
+
+/// Maximum number of years that the BLS API returns in a single request. The limit
+/// is 20 years for registered users, but only 10 years for anonymous ones.
+const MAX_YEARS_PER_REQUEST: i32 = 10;
+
+pub type BlsApiError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Client for the BLS Public Data API v2, used to fetch individual CPI average
+/// price series (series IDs in the format `APU{area_code}{item_code}`).
+pub struct BlsApiClient {
+    url: String,
+    registration_key_or: Option<String>,
+    http_client: reqwest::Client,
+}
+
+impl BlsApiClient {
+    pub fn new(url: String, registration_key_or: Option<String>) -> Self {
+        Self {
+            url,
+            registration_key_or,
+            http_client: build_http_client(),
+        }
+    }
+
+    /// Configures a client from the `SATDASH_BLS_API_URL` and `SATDASH_BLS_API_KEY`
+    /// environment variables. If no URL is set, the public BLS endpoint is used.
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("SATDASH_BLS_API_URL")
+                .unwrap_or_else(|_| DEFAULT_BLS_API_URL.to_string()),
+            std::env::var("SATDASH_BLS_API_KEY").ok(),
+        )
+    }
+
+    /// Fetches monthly values for each series between `start_year` and `end_year`
+    /// (inclusive). Requests are split up as needed to stay within API limits.
+    pub async fn fetch_series(
+        &self,
+        series_ids: &[String],
+        start_year: i32,
+        end_year: i32,
+    ) -> Result<Vec<SeriesEntry>, BlsApiError> {
+        for series_id in series_ids {
+            if !is_average_price_series_id(series_id) {
+                return Err(Box::from(format!(
+                    "Not a CPI average price series ID: {}",
+                    series_id
+                )));
+            }
+        }
+
+        let mut series_entries = Vec::new();
+
+        for series_id_chunk in series_ids.chunks(MAX_SERIES_PER_REQUEST) {
+            let mut chunk_start_year = start_year;
+            while chunk_start_year <= end_year {
+                let chunk_end_year =
+                    std::cmp::min(chunk_start_year + MAX_YEARS_PER_REQUEST - 1, end_year);
+
+                let request = BlsApiRequest {
+                    seriesid: series_id_chunk,
+                    startyear: chunk_start_year.to_string(),
+                    endyear: chunk_end_year.to_string(),
+                    registrationkey: self.registration_key_or.as_deref(),
+                };
+                let response_body = self
+                    .http_client
+                    .post(&self.url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(serde_json::to_string(&request)?)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await?;
+                let response: BlsApiResponse = serde_json::from_str(&response_body)?;
+
+                if response.status != "REQUEST_SUCCEEDED" {
+                    return Err(Box::from(format!(
+                        "BLS API request failed with status {}: {}",
+                        response.status,
+                        response.message.join(" ")
+                    )));
+                }
+
+                for series in response
+                    .results
+                    .map(|results| results.series)
+                    .unwrap_or_default()
+                {
+                    series_entries.extend(series.data.iter().filter_map(|data_point| {
+                        // Skip annual averages (`M13`) and any other non-monthly periods.
+                        let month = data_point.period.strip_prefix('M')?.parse::<u32>().ok()?;
+                        if !(1..=12).contains(&month)
+                            || data_point.year.parse::<i32>().is_err()
+                            || data_point.value.parse::<f64>().is_err()
+                        {
+                            return None;
+                        }
+
+                        SeriesEntry::new_from_parts_or(
+                            &series.series_id,
+                            &data_point.year,
+                            &data_point.period,
+                            &data_point.value,
+                        )
+                    }));
+                }
+
+                chunk_start_year = chunk_end_year + 1;
+            }
+        }
+
+        Ok(series_entries)
+    }
+}
+
+/// Checks that a series ID is an unadjusted average price series with
+/// enough characters to contain both an area code and an item code.
+fn is_average_price_series_id(series_id: &str) -> bool {
+    series_id.starts_with("APU") && series_id.len() > 7 && series_id.is_ascii()
+}
+
+#[derive(Serialize)]
+struct BlsApiRequest<'a> {
+    seriesid: &'a [String],
+    startyear: String,
+    endyear: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    registrationkey: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct BlsApiResponse {
+    status: String,
+    #[serde(default)]
+    message: Vec<String>,
+    #[serde(rename = "Results")]
+    results: Option<BlsApiResults>,
+}
+
+#[derive(Deserialize)]
+struct BlsApiResults {
+    /// Failed requests have an empty `Results` object.
+    #[serde(default)]
+    series: Vec<BlsApiSeries>,
+}
+
+#[derive(Deserialize)]
+struct BlsApiSeries {
+    #[serde(rename = "seriesID")]
+    series_id: String,
+    data: Vec<BlsApiDataPoint>,
+}
+
+#[derive(Deserialize)]
+struct BlsApiDataPoint {
+    year: String,
+    /// In the format `Mxx`, same as the flat files.
+    period: String,
+    value: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bpi::test_server::TestServer;
+
+    /// A recorded response for `APU0000701111` and `APU0000708111` in 2023. It
+    /// includes an annual average (`M13`) and a missing value (`-`).
+    const RESPONSE_FIXTURE: &str = include_str!("./test_fixtures/bls_api_v2_response.json");
+
+    const NOT_PROCESSED_RESPONSE_FIXTURE: &str = r#"{
+        "status": "REQUEST_NOT_PROCESSED",
+        "responseTime": 0,
+        "message": ["Daily threshold for total number of requests allocated to the user has been reached."],
+        "Results": {}
+    }"#;
+
+    fn get_series_ids(count: usize) -> Vec<String> {
+        (0..count)
+            .map(|index| format!("APU0000{:06}", 700000 + index))
+            .collect()
+    }
+
+    #[rocket::async_test]
+    async fn parses_monthly_values() {
+        let test_server = TestServer::start_with_response(200, RESPONSE_FIXTURE).await;
+        let bls_api_client = BlsApiClient::new(test_server.get_url("/"), None);
+
+        let series_entries = bls_api_client
+            .fetch_series(&get_series_ids(2), 2023, 2023)
+            .await
+            .unwrap();
+
+        // The `M13` annual average and the missing value are skipped.
+        let values: Vec<(&str, &str, i32, u32, f64)> = series_entries
+            .iter()
+            .map(|series_entry| {
+                (
+                    series_entry.get_area_code().as_str(),
+                    series_entry.get_item_code().as_str(),
+                    series_entry.get_year(),
+                    series_entry.get_month(),
+                    series_entry.get_value(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                ("0000", "701111", 2023, 2, 0.541),
+                ("0000", "701111", 2023, 1, 0.533),
+                ("0000", "708111", 2023, 2, 4.211),
+                ("0000", "708111", 2023, 1, 4.823),
+            ]
+        );
+
+        let requests = test_server.get_requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+        let request_body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(
+            request_body,
+            serde_json::json!({
+                "seriesid": ["APU0000700000", "APU0000700001"],
+                "startyear": "2023",
+                "endyear": "2023",
+            })
+        );
+    }
+
+    #[rocket::async_test]
+    async fn splits_requests_by_series_and_years() {
+        let test_server = TestServer::start_with_response(200, RESPONSE_FIXTURE).await;
+        let bls_api_client =
+            BlsApiClient::new(test_server.get_url("/"), Some("test-key".to_string()));
+
+        bls_api_client
+            .fetch_series(&get_series_ids(51), 2000, 2020)
+            .await
+            .unwrap();
+
+        let request_chunks: Vec<(usize, String, String, String)> = test_server
+            .get_requests()
+            .iter()
+            .map(|request| {
+                let request_body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+                (
+                    request_body["seriesid"].as_array().unwrap().len(),
+                    request_body["startyear"].as_str().unwrap().to_string(),
+                    request_body["endyear"].as_str().unwrap().to_string(),
+                    request_body["registrationkey"]
+                        .as_str()
+                        .unwrap()
+                        .to_string(),
+                )
+            })
+            .collect();
+        let expected_request_chunks: Vec<(usize, String, String, String)> = [
+            (50, "2000", "2009"),
+            (50, "2010", "2019"),
+            (50, "2020", "2020"),
+            (1, "2000", "2009"),
+            (1, "2010", "2019"),
+            (1, "2020", "2020"),
+        ]
+        .iter()
+        .map(|(series_count, start_year, end_year)| {
+            (
+                *series_count,
+                start_year.to_string(),
+                end_year.to_string(),
+                "test-key".to_string(),
+            )
+        })
+        .collect();
+        assert_eq!(request_chunks, expected_request_chunks);
+    }
+
+    #[rocket::async_test]
+    async fn fails_when_request_not_processed() {
+        let test_server =
+            TestServer::start_with_response(200, NOT_PROCESSED_RESPONSE_FIXTURE).await;
+        let bls_api_client = BlsApiClient::new(test_server.get_url("/"), None);
+
+        let error = bls_api_client
+            .fetch_series(&get_series_ids(1), 2023, 2023)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "BLS API request failed with status REQUEST_NOT_PROCESSED: Daily threshold for \
+             total number of requests allocated to the user has been reached."
+        );
+    }
+
+    #[rocket::async_test]
+    async fn rejects_other_series_ids_without_requesting() {
+        let test_server = TestServer::start_with_response(200, RESPONSE_FIXTURE).await;
+        let bls_api_client = BlsApiClient::new(test_server.get_url("/"), None);
+
+        assert!(bls_api_client
+            .fetch_series(&["CUUR0000SA0".to_string()], 2023, 2023)
+            .await
+            .is_err());
+        assert!(test_server.get_requests().is_empty());
+    }
+}
@@ -1,109 +1,480 @@
+use super::btc_price_provider::BTCPriceProvider;
 use super::dated_series::DatedSeries;
-use chrono::{Date, TimeZone, Utc};
-use serde::Deserialize;
+use chrono::{Datelike, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Which daily Bitcoin price is used when pricing things in sats.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PriceBasis {
+    Open,
+    High,
+    Low,
+    Close,
+    /// Volume-weighted average price over each calendar month. Stored on the 1st
+    /// of each month, which is also where monthly CPI data points are stored.
+    Vwap,
+}
+
+impl<'a> rocket::form::FromFormField<'a> for PriceBasis {
+    fn from_value(field: rocket::form::ValueField<'a>) -> rocket::form::Result<'a, Self> {
+        match field.value {
+            "open" => Ok(Self::Open),
+            "high" => Ok(Self::High),
+            "low" => Ok(Self::Low),
+            "close" => Ok(Self::Close),
+            "vwap" => Ok(Self::Vwap),
+            _ => Err(rocket::form::Error::validation(format!(
+                "Unknown price basis: {}",
+                field.value
+            ))
+            .into()),
+        }
+    }
+}
+
+/// Daily OHLCV Bitcoin price data. Any column that a provider
+/// doesn't have is `None`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BTCPriceColumns {
+    open_or: Option<DatedSeries>,
+    high_or: Option<DatedSeries>,
+    low_or: Option<DatedSeries>,
+    close_or: Option<DatedSeries>,
+    volume_or: Option<DatedSeries>,
+    /// Derived from the other columns when the price data is built.
+    vwap_or: Option<DatedSeries>,
+}
+
+impl BTCPriceColumns {
+    pub fn new(
+        open_or: Option<DatedSeries>,
+        high_or: Option<DatedSeries>,
+        low_or: Option<DatedSeries>,
+        close_or: Option<DatedSeries>,
+        volume_or: Option<DatedSeries>,
+    ) -> Self {
+        let mut price_columns = Self {
+            open_or,
+            high_or,
+            low_or,
+            close_or,
+            volume_or,
+            vwap_or: None,
+        };
+        price_columns.vwap_or = price_columns.compute_vwap_or();
+        price_columns
+    }
+
+    pub fn get(&self, price_basis: PriceBasis) -> Option<&DatedSeries> {
+        let series_or = match price_basis {
+            PriceBasis::Open => &self.open_or,
+            PriceBasis::High => &self.high_or,
+            PriceBasis::Low => &self.low_or,
+            PriceBasis::Close => &self.close_or,
+            PriceBasis::Vwap => &self.vwap_or,
+        };
+
+        // Empty columns are treated the same as missing ones.
+        series_or
+            .as_ref()
+            .filter(|series| series.get_last_entry_date().is_some())
+    }
+
+    fn has_any_prices(&self) -> bool {
+        [
+            PriceBasis::Open,
+            PriceBasis::High,
+            PriceBasis::Low,
+            PriceBasis::Close,
+        ]
+        .iter()
+        .any(|price_basis| self.get(*price_basis).is_some())
+    }
+
+    /// Combines this data with newer or higher-priority data, column by column.
+    /// Wherever both have a value for the same date, the value from
+    /// `newer_price_columns` is kept.
+    fn merge(&self, newer_price_columns: &Self) -> Self {
+        fn merge_column(
+            series_or: &Option<DatedSeries>,
+            newer_series_or: &Option<DatedSeries>,
+        ) -> Option<DatedSeries> {
+            match (series_or, newer_series_or) {
+                (Some(series), Some(newer_series)) => Some(series.merge(newer_series)),
+                (Some(series), None) => Some(series.clone()),
+                (None, newer_series_or) => newer_series_or.clone(),
+            }
+        }
+
+        Self::new(
+            merge_column(&self.open_or, &newer_price_columns.open_or),
+            merge_column(&self.high_or, &newer_price_columns.high_or),
+            merge_column(&self.low_or, &newer_price_columns.low_or),
+            merge_column(&self.close_or, &newer_price_columns.close_or),
+            merge_column(&self.volume_or, &newer_price_columns.volume_or),
+        )
+    }
+
+    /// Computes the monthly volume-weighted average of each day's typical price,
+    /// `(high + low + close) / 3`. Days without a high and low use the close
+    /// price, and days without any volume data are skipped.
+    fn compute_vwap_or(&self) -> Option<DatedSeries> {
+        let volume_series = self.volume_or.as_ref()?;
+        let close_series = self.close_or.as_ref()?;
+
+        // Maps the first day of each month to its total (price * volume) and volume.
+        let mut totals_by_month = HashMap::new();
+
+        for (date, volume) in volume_series.iter() {
+            let close = match close_series.get_price(date) {
+                Some(close) => close,
+                None => continue,
+            };
+            let high_or = self.high_or.as_ref().and_then(|high| high.get_price(date));
+            let low_or = self.low_or.as_ref().and_then(|low| low.get_price(date));
+            let typical_price = match (high_or, low_or) {
+                (Some(high), Some(low)) => (high + low + close) / 3.0,
+                _ => close,
+            };
+
+            let (price_volume_total, volume_total) = totals_by_month
+                .entry(Utc.ymd(date.year(), date.month(), 1))
+                .or_insert((0.0, 0.0));
+            *price_volume_total += typical_price * volume;
+            *volume_total += volume;
+        }
+
+        Some(DatedSeries::new(
+            totals_by_month
+                .into_iter()
+                .filter(|(_, (_, volume_total))| *volume_total > 0.0)
+                .map(|(month, (price_volume_total, volume_total))| {
+                    (month, price_volume_total / volume_total)
+                })
+                .collect(),
+        ))
+    }
+}
 
 pub struct BTCPriceHistory {
-    /// Fallback Bitcoin price data that's stored
-    /// in the binary in case API data can't be loaded.
-    csv_price_by_date: DatedSeries,
-    // /// Dynamically-loaded up-to-date Bitcoin price data. Used by default.
-    // api_loaded_price_by_date_or: Option<DatedSeries>,
+    /// All registered price sources, ordered from highest to lowest priority.
+    /// At least one of these is guaranteed to have loaded successfully.
+    sources: Vec<PriceSource>,
+    /// Price data from every source merged together. See `merge_sources`.
+    merged_price_columns: BTCPriceColumns,
+}
+
+struct PriceSource {
+    provider: Arc<dyn BTCPriceProvider>,
+    /// Price data from the most recent successful load, if any.
+    price_columns_or: Option<BTCPriceColumns>,
 }
 
 impl BTCPriceHistory {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let mut csv_price_by_date = HashMap::new();
-        let mut rdr = csv::Reader::from_reader(include_bytes!("./BTC-USD.csv") as &[u8]);
-        for result in rdr.deserialize() {
-            let entry: BTCPriceCSVEntry = result?;
-            csv_price_by_date.insert(
-                convert_date_string_to_date(&entry.date).unwrap(),
-                entry.open.parse::<f64>().unwrap(),
-            );
+    /// Loads price data from every provider. Fails only if none of them
+    /// return any usable data.
+    pub async fn new(
+        providers: Vec<Box<dyn BTCPriceProvider>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut sources = Vec::new();
+
+        for provider in providers {
+            let provider: Arc<dyn BTCPriceProvider> = Arc::from(provider);
+            let price_columns_or = load_price_source(provider.as_ref()).await;
+            sources.push(PriceSource {
+                provider,
+                price_columns_or,
+            });
         }
 
-        // let api_loaded_price_by_date_or = match load_api_btc_price_data().await {
-        //     Ok(api_loaded_price_by_date) => Some(api_loaded_price_by_date),
-        //     Err(_) => None,
-        // };
+        let btc_price_history = match Self::from_sources(sources) {
+            Some(btc_price_history) => btc_price_history,
+            None => return Err(Box::from("No BTC price source could be loaded")),
+        };
+        println!(
+            "Using BTC price sources {}.",
+            btc_price_history
+                .sources
+                .iter()
+                .filter(|source| source.price_columns_or.is_some())
+                .map(|source| source.provider.get_name())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+
+        Ok(btc_price_history)
+    }
 
-        Ok(Self {
-            csv_price_by_date: DatedSeries::new(csv_price_by_date),
-            // api_loaded_price_by_date_or,
+    /// Returns `None` if none of the sources have any price data.
+    fn from_sources(sources: Vec<PriceSource>) -> Option<Self> {
+        let merged_price_columns = merge_sources(&sources)?;
+        Some(Self {
+            sources,
+            merged_price_columns,
         })
     }
 
-    /// Attempts to return API-loaded price data if available, falling
-    /// back to preloaded CSV data if necessary.
-    pub fn get_best_dataset(&self) -> &DatedSeries {
-        // TODO - This line is a hotfix due to breakage of the current
-        // Bitcoin price data API. Remove this and uncomment the block
-        // below it once things are working again. We probably need to
-        // start using a different price API.
-        &self.csv_price_by_date
-        // match &self.api_loaded_price_by_date_or {
-        //     Some(api_loaded_price_by_date) => api_loaded_price_by_date,
-        //     None => &self.csv_price_by_date,
-        // }
+    /// Loads the latest prices from every provider, in priority order. Sources that
+    /// fail to load are `None`. The price history itself isn't changed, so slow
+    /// providers can be waited on without holding up other updates. The result is
+    /// applied with `with_latest_prices`.
+    pub async fn load_latest_prices(&self) -> Vec<Option<BTCPriceColumns>> {
+        let mut latest_price_columns = Vec::new();
+        for source in &self.sources {
+            latest_price_columns.push(load_price_source(source.provider.as_ref()).await);
+        }
+        latest_price_columns
     }
-}
 
-/// Converts a string in the format `yyyy-mm-dd` to a Date object.
-fn convert_date_string_to_date(date_string: &str) -> Result<Date<Utc>, Box<dyn std::error::Error>> {
-    let mut date_parts_iter = date_string.split('-');
-    let year = date_parts_iter.next().unwrap_or_default().parse::<i32>()?;
-    let month = date_parts_iter.next().unwrap_or_default().parse::<u32>()?;
-    let day = date_parts_iter.next().unwrap_or_default().parse::<u32>()?;
+    /// Returns an updated copy of the price history with prices returned by
+    /// `load_latest_prices` merged into each source's previously-loaded data.
+    /// Sources that failed to reload keep their previous data.
+    pub fn with_latest_prices(&self, latest_price_columns: Vec<Option<BTCPriceColumns>>) -> Self {
+        let sources = self
+            .sources
+            .iter()
+            .zip(latest_price_columns)
+            .map(|(source, latest_price_columns_or)| {
+                let price_columns_or = match (&source.price_columns_or, latest_price_columns_or) {
+                    (Some(price_columns), Some(latest_price_columns)) => {
+                        Some(price_columns.merge(&latest_price_columns))
+                    }
+                    (None, Some(latest_price_columns)) => Some(latest_price_columns),
+                    (price_columns_or, None) => price_columns_or.clone(),
+                };
+
+                PriceSource {
+                    provider: source.provider.clone(),
+                    price_columns_or,
+                }
+            })
+            .collect();
+
+        // Note: unwrap is safe here because sources never lose data, and at least
+        // one of them had some to begin with.
+        Self::from_sources(sources).unwrap()
+    }
+
+    /// Rebuilds a price history from the price data previously returned by
+    /// `get_snapshot_price_columns`, paired with the same providers in the same
+    /// order. Returns `None` if the providers don't line up or none had data.
+    pub fn from_snapshot(
+        providers: Vec<Box<dyn BTCPriceProvider>>,
+        snapshot_price_columns: Vec<Option<BTCPriceColumns>>,
+    ) -> Option<Self> {
+        if providers.len() != snapshot_price_columns.len() {
+            return None;
+        }
 
-    match Utc.ymd_opt(year, month, day) {
-        chrono::offset::LocalResult::Single(date) => Ok(date),
-        _ => Err(Box::from(format!(
-            "Cannot parse date string: {}",
-            date_string
-        ))),
+        Self::from_sources(
+            providers
+                .into_iter()
+                .zip(snapshot_price_columns)
+                .map(|(provider, price_columns_or)| PriceSource {
+                    provider: Arc::from(provider),
+                    price_columns_or,
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns each source's price data, in priority order.
+    pub fn get_snapshot_price_columns(&self) -> Vec<Option<&BTCPriceColumns>> {
+        self.sources
+            .iter()
+            .map(|source| source.price_columns_or.as_ref())
+            .collect()
+    }
+
+    /// Returns the name of each source, in priority order.
+    pub fn get_provider_names(&self) -> Vec<String> {
+        self.sources
+            .iter()
+            .map(|source| source.provider.get_name())
+            .collect()
+    }
+
+    /// Returns the price data for `price_basis`, merged from every source that has it.
+    pub fn get_dataset(&self, price_basis: PriceBasis) -> Option<&DatedSeries> {
+        self.merged_price_columns.get(price_basis)
     }
 }
 
-#[derive(Deserialize)]
-struct BTCPriceCSVEntry {
-    /// Should always be in format "yyyy-mm-dd"
-    date: String,
-    open: String,
+/// Merges the price data of every source that has any, layering them from lowest
+/// to highest priority. Lower-priority sources fill in the dates that higher-priority
+/// ones don't cover, e.g. the embedded CSV's history before a recent window fetched
+/// over HTTP. Where sources overlap, the highest-priority source's price wins.
+fn merge_sources(sources: &[PriceSource]) -> Option<BTCPriceColumns> {
+    let mut loaded_price_columns_iter = sources
+        .iter()
+        .rev()
+        .filter_map(|source| source.price_columns_or.as_ref());
+
+    let lowest_priority_price_columns = loaded_price_columns_iter.next()?.clone();
+    Some(loaded_price_columns_iter.fold(
+        lowest_priority_price_columns,
+        |merged_price_columns, price_columns| merged_price_columns.merge(price_columns),
+    ))
+}
+
+/// Loads prices from a single provider, logging and discarding any failures.
+async fn load_price_source(provider: &dyn BTCPriceProvider) -> Option<BTCPriceColumns> {
+    match provider.load_prices().await {
+        Ok(price_columns) if price_columns.has_any_prices() => Some(price_columns),
+        Ok(_) => {
+            eprintln!("BTC price source {} returned no data.", provider.get_name());
+            None
+        }
+        Err(e) => {
+            eprintln!(
+                "Failed to load BTC price source {}: {}",
+                provider.get_name(),
+                e
+            );
+            None
+        }
+    }
 }
 
-// async fn load_api_btc_price_data() -> Result<DatedSeries, Box<dyn std::error::Error>> {
-//     let bitcoin_price_api_resp_string = reqwest::get(
-//         "https://api.statmuse.com/money/assets/64a686bd-2876-4636-bdf1-f83febe1dbb3/price?frequency=Day"
-//     ).await?.text().await?;
-//     let bitcoin_price_api_resp: BitcoinPriceApiResponse =
-//         serde_json::from_str(&bitcoin_price_api_resp_string).unwrap();
-//     let mut api_loaded_price_by_date = HashMap::new();
-//     for entry in bitcoin_price_api_resp.prices {
-//         if let Some(open) = entry.open {
-//             api_loaded_price_by_date.insert(
-//                 convert_date_string_to_date(&entry.timeframe.timestamp).unwrap(),
-//                 open,
-//             );
-//         }
-//     }
-
-//     Ok(DatedSeries::new(api_loaded_price_by_date))
-// }
-
-// #[derive(Deserialize)]
-// struct BitcoinPriceApiResponse {
-//     prices: Vec<BitcoinPriceApiEntry>,
-// }
-
-// #[derive(Deserialize)]
-// struct BitcoinPriceApiEntry {
-//     // timeframe: BitcoinPriceApiTimeframe,
-//     // open: Option<f64>,
-// }
-
-// #[derive(Deserialize)]
-// struct BitcoinPriceApiTimeframe {
-//     /// Should always be in format "yyyy-mm-dd"
-//     timestamp: String,
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bpi::btc_price_provider::ProviderFuture;
+    use chrono::Date;
+
+    /// Returns the same prices on every load, or fails if it has none.
+    struct TestProvider {
+        name: &'static str,
+        price_columns_or: Option<BTCPriceColumns>,
+    }
+
+    impl BTCPriceProvider for TestProvider {
+        fn get_name(&self) -> String {
+            self.name.to_string()
+        }
+
+        fn load_prices(&self) -> ProviderFuture<'_> {
+            Box::pin(async move {
+                self.price_columns_or
+                    .clone()
+                    .ok_or_else(|| Box::from(format!("{} is unavailable", self.name)))
+            })
+        }
+    }
+
+    fn get_test_provider(
+        name: &'static str,
+        prices: &[(Date<Utc>, f64)],
+    ) -> Box<dyn BTCPriceProvider> {
+        let series = DatedSeries::new(prices.iter().cloned().collect());
+        Box::from(TestProvider {
+            name,
+            price_columns_or: Some(BTCPriceColumns::new(
+                Some(series.clone()),
+                None,
+                None,
+                Some(series),
+                None,
+            )),
+        })
+    }
+
+    fn get_failing_provider(name: &'static str) -> Box<dyn BTCPriceProvider> {
+        Box::from(TestProvider {
+            name,
+            price_columns_or: None,
+        })
+    }
+
+    #[rocket::async_test]
+    async fn falls_back_when_a_provider_fails() {
+        let btc_price_history = BTCPriceHistory::new(vec![
+            get_failing_provider("primary"),
+            get_test_provider("fallback", &[(Utc.ymd(2023, 9, 1), 26000.0)]),
+        ])
+        .await
+        .unwrap();
+
+        let open = btc_price_history.get_dataset(PriceBasis::Open).unwrap();
+        assert_eq!(open.get_price(Utc.ymd(2023, 9, 1)), Some(26000.0));
+        assert!(btc_price_history.get_dataset(PriceBasis::High).is_none());
+    }
+
+    #[rocket::async_test]
+    async fn fails_when_every_provider_fails() {
+        assert!(BTCPriceHistory::new(vec![
+            get_failing_provider("primary"),
+            get_failing_provider("fallback"),
+        ])
+        .await
+        .is_err());
+    }
+
+    #[rocket::async_test]
+    async fn merges_sources_by_priority() {
+        let btc_price_history = BTCPriceHistory::new(vec![
+            get_test_provider(
+                "recent window",
+                &[
+                    (Utc.ymd(2023, 9, 2), 25500.0),
+                    (Utc.ymd(2023, 9, 3), 25600.0),
+                ],
+            ),
+            get_failing_provider("unavailable"),
+            get_test_provider(
+                "full history",
+                &[
+                    (Utc.ymd(2014, 9, 17), 465.86),
+                    (Utc.ymd(2023, 9, 2), 24000.0),
+                ],
+            ),
+        ])
+        .await
+        .unwrap();
+
+        let open = btc_price_history.get_dataset(PriceBasis::Open).unwrap();
+        assert_eq!(open.get_first_entry_date(), Some(&Utc.ymd(2014, 9, 17)));
+        assert_eq!(open.get_last_entry_date(), Some(&Utc.ymd(2023, 9, 3)));
+        assert_eq!(open.get_price(Utc.ymd(2014, 9, 17)), Some(465.86));
+        assert_eq!(open.get_price(Utc.ymd(2023, 9, 2)), Some(25500.0));
+    }
+
+    #[rocket::async_test]
+    async fn merges_latest_prices_into_previous_data() {
+        let btc_price_history = BTCPriceHistory::new(vec![
+            get_test_provider("primary", &[(Utc.ymd(2023, 9, 1), 26000.0)]),
+            get_test_provider("fallback", &[(Utc.ymd(2023, 8, 31), 25000.0)]),
+        ])
+        .await
+        .unwrap();
+
+        let latest_price_columns = vec![
+            Some(BTCPriceColumns::new(
+                Some(DatedSeries::new(
+                    [(Utc.ymd(2023, 9, 2), 26100.0)].iter().cloned().collect(),
+                )),
+                None,
+                None,
+                None,
+                None,
+            )),
+            // The fallback failed to reload, so it keeps its previous data.
+            None,
+        ];
+        let btc_price_history = btc_price_history.with_latest_prices(latest_price_columns);
+
+        let open = btc_price_history.get_dataset(PriceBasis::Open).unwrap();
+        assert_eq!(open.get_price(Utc.ymd(2023, 8, 31)), Some(25000.0));
+        assert_eq!(open.get_price(Utc.ymd(2023, 9, 1)), Some(26000.0));
+        assert_eq!(open.get_price(Utc.ymd(2023, 9, 2)), Some(26100.0));
+        assert_eq!(
+            btc_price_history
+                .get_dataset(PriceBasis::Close)
+                .unwrap()
+                .get_last_entry_date(),
+            Some(&Utc.ymd(2023, 9, 1))
+        );
+    }
+}
@@ -0,0 +1,448 @@
+use super::btc_price_history::BTCPriceColumns;
+use super::dated_series::DatedSeries;
+use chrono::{Date, TimeZone, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// How long HTTP requests wait to connect, and to finish entirely, before failing.
+/// Providers are loaded before the server starts, so a source that never responds
+/// mustn't be able to hold it up forever.
+const HTTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const HTTP_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub type ProviderError = Box<dyn std::error::Error + Send + Sync>;
+
+pub type ProviderFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<BTCPriceColumns, ProviderError>> + Send + 'a>>;
+
+/// A source of daily Bitcoin price data. Providers are registered with
+/// `BTCPriceHistory` in priority order, and may fail to load at any time.
+pub trait BTCPriceProvider: Send + Sync {
+    /// Human-readable name used when logging which source is being used.
+    fn get_name(&self) -> String;
+
+    /// Loads the full price history available from this provider.
+    fn load_prices(&self) -> ProviderFuture<'_>;
+}
+
+/// Builds the default provider chain, ordered from highest to lowest priority.
+/// Optional providers are configured through environment variables:
+///
+/// * `SATDASH_BTC_PRICE_URLS` - Comma-separated list of `<format>=<url>` pairs.
+///   See `HttpJsonFormat` for the supported formats.
+/// * `SATDASH_BTC_PRICE_FILE` - Path to a CSV file in the same format as `BTC-USD.csv`.
+///
+/// The embedded CSV is always registered last so there's something to fall back to.
+pub fn get_default_providers() -> Vec<Box<dyn BTCPriceProvider>> {
+    let mut providers: Vec<Box<dyn BTCPriceProvider>> = Vec::new();
+
+    if let Ok(urls) = std::env::var("SATDASH_BTC_PRICE_URLS") {
+        let http_client = build_http_client();
+        for source in urls.split(',').map(|source| source.trim()) {
+            if source.is_empty() {
+                continue;
+            }
+            match HttpJsonProvider::from_source_string(source, http_client.clone()) {
+                Ok(provider) => providers.push(Box::from(provider)),
+                Err(e) => eprintln!("Skipping BTC price source '{}': {}", source, e),
+            }
+        }
+    }
+
+    if let Ok(path) = std::env::var("SATDASH_BTC_PRICE_FILE") {
+        providers.push(Box::from(LocalCsvProvider::new(path)));
+    }
+
+    providers.push(Box::from(EmbeddedCsvProvider));
+
+    providers
+}
+
+/// Builds an HTTP client with connect and request timeouts. Clients share a
+/// connection pool between their clones, so one is built and cloned for each user.
+pub fn build_http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .connect_timeout(HTTP_CONNECT_TIMEOUT)
+        .timeout(HTTP_REQUEST_TIMEOUT)
+        .build()
+        .expect("Failed to build HTTP client")
+}
+
+/// Bitcoin price data that's stored in the binary.
+pub struct EmbeddedCsvProvider;
+
+impl BTCPriceProvider for EmbeddedCsvProvider {
+    fn get_name(&self) -> String {
+        "embedded CSV".to_string()
+    }
+
+    fn load_prices(&self) -> ProviderFuture<'_> {
+        Box::pin(async { parse_price_csv(include_bytes!("./BTC-USD.csv") as &[u8]) })
+    }
+}
+
+/// Bitcoin price data read from a CSV file on disk. The file is re-read
+/// every time prices are loaded, so it can be updated while the server runs.
+pub struct LocalCsvProvider {
+    path: String,
+}
+
+impl LocalCsvProvider {
+    pub fn new(path: String) -> Self {
+        Self { path }
+    }
+}
+
+impl BTCPriceProvider for LocalCsvProvider {
+    fn get_name(&self) -> String {
+        format!("CSV file '{}'", self.path)
+    }
+
+    fn load_prices(&self) -> ProviderFuture<'_> {
+        Box::pin(async move {
+            let file_bytes = rocket::tokio::fs::read(&self.path).await?;
+            parse_price_csv(file_bytes.as_slice())
+        })
+    }
+}
+
+/// The JSON response shapes understood by `HttpJsonProvider`.
+#[derive(Clone, Copy)]
+pub enum HttpJsonFormat {
+    /// CoinGecko's `/coins/{id}/market_chart` response, which contains
+    /// `{"prices": [[<unix millis>, <price>], ...], "total_volumes": [...]}`.
+    /// Provides open and close prices, as well as volume.
+    CoinGecko,
+    /// A plain list of `[{"date": "yyyy-mm-dd", "price": <price>}, ...]`. Since
+    /// there's only one price per day, it's used as both the open and close price.
+    DatePriceList,
+}
+
+impl HttpJsonFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "coingecko" => Some(Self::CoinGecko),
+            "date-price-list" => Some(Self::DatePriceList),
+            _ => None,
+        }
+    }
+
+    fn parse(&self, body: &str) -> Result<BTCPriceColumns, ProviderError> {
+        match self {
+            Self::CoinGecko => {
+                let response: CoinGeckoMarketChartResponse = serde_json::from_str(body)?;
+
+                let mut open_by_date = HashMap::new();
+                let mut close_by_date = HashMap::new();
+                // CoinGecko can return more than one price per day. They're sorted
+                // chronologically, so the first one is closest to the day's open
+                // and the last one is closest to the day's close.
+                for (timestamp_millis, price) in response.prices {
+                    let date = convert_timestamp_millis_to_date(timestamp_millis)?;
+                    open_by_date.entry(date).or_insert(price);
+                    close_by_date.insert(date, price);
+                }
+
+                let mut volume_by_date = HashMap::new();
+                for (timestamp_millis, volume) in response.total_volumes {
+                    volume_by_date
+                        .insert(convert_timestamp_millis_to_date(timestamp_millis)?, volume);
+                }
+
+                Ok(BTCPriceColumns::new(
+                    Some(DatedSeries::new(open_by_date)),
+                    None,
+                    None,
+                    Some(DatedSeries::new(close_by_date)),
+                    Some(DatedSeries::new(volume_by_date)),
+                ))
+            }
+            Self::DatePriceList => {
+                let entries: Vec<DatePriceEntry> = serde_json::from_str(body)?;
+
+                let mut price_by_date = HashMap::new();
+                for entry in entries {
+                    price_by_date.insert(convert_date_string_to_date(&entry.date)?, entry.price);
+                }
+
+                let price_series = DatedSeries::new(price_by_date);
+                Ok(BTCPriceColumns::new(
+                    Some(price_series.clone()),
+                    None,
+                    None,
+                    Some(price_series),
+                    None,
+                ))
+            }
+        }
+    }
+}
+
+/// Bitcoin price data fetched from an HTTP endpoint that returns JSON.
+pub struct HttpJsonProvider {
+    url: String,
+    format: HttpJsonFormat,
+    http_client: reqwest::Client,
+}
+
+impl HttpJsonProvider {
+    /// `http_client` should come from `build_http_client`, so that requests time out.
+    pub fn new(url: String, format: HttpJsonFormat, http_client: reqwest::Client) -> Self {
+        Self {
+            url,
+            format,
+            http_client,
+        }
+    }
+
+    /// Parses a source string in the format `<format>=<url>`,
+    /// e.g. `coingecko=https://example.com/prices`.
+    fn from_source_string(source: &str, http_client: reqwest::Client) -> Result<Self, String> {
+        let (format_name, url) = source
+            .split_once('=')
+            .ok_or_else(|| "Expected format `<format>=<url>`".to_string())?;
+        let format = HttpJsonFormat::from_name(format_name)
+            .ok_or_else(|| format!("Unknown price format '{}'", format_name))?;
+        Ok(Self::new(url.to_string(), format, http_client))
+    }
+}
+
+impl BTCPriceProvider for HttpJsonProvider {
+    fn get_name(&self) -> String {
+        format!("HTTP JSON '{}'", self.url)
+    }
+
+    fn load_prices(&self) -> ProviderFuture<'_> {
+        Box::pin(async move {
+            let body = self
+                .http_client
+                .get(&self.url)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+            self.format.parse(&body)
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct CoinGeckoMarketChartResponse {
+    prices: Vec<(f64, f64)>,
+    #[serde(default)]
+    total_volumes: Vec<(f64, f64)>,
+}
+
+#[derive(Deserialize)]
+struct DatePriceEntry {
+    /// Should always be in format "yyyy-mm-dd"
+    date: String,
+    price: f64,
+}
+
+/// Parses CSV data with the same columns as `BTC-USD.csv`. Values that
+/// can't be parsed (e.g. `null`) are skipped.
+fn parse_price_csv(csv_bytes: &[u8]) -> Result<BTCPriceColumns, ProviderError> {
+    let mut open_by_date = HashMap::new();
+    let mut high_by_date = HashMap::new();
+    let mut low_by_date = HashMap::new();
+    let mut close_by_date = HashMap::new();
+    let mut volume_by_date = HashMap::new();
+
+    let mut rdr = csv::Reader::from_reader(csv_bytes);
+    for result in rdr.deserialize() {
+        let entry: BTCPriceCSVEntry = result?;
+        let date = convert_date_string_to_date(&entry.date)?;
+
+        for (value, value_by_date) in [
+            (&entry.open, &mut open_by_date),
+            (&entry.high, &mut high_by_date),
+            (&entry.low, &mut low_by_date),
+            (&entry.close, &mut close_by_date),
+            (&entry.volume, &mut volume_by_date),
+        ] {
+            if let Ok(value) = value.parse::<f64>() {
+                value_by_date.insert(date, value);
+            }
+        }
+    }
+
+    Ok(BTCPriceColumns::new(
+        Some(DatedSeries::new(open_by_date)),
+        Some(DatedSeries::new(high_by_date)),
+        Some(DatedSeries::new(low_by_date)),
+        Some(DatedSeries::new(close_by_date)),
+        Some(DatedSeries::new(volume_by_date)),
+    ))
+}
+
+/// Converts a Unix timestamp in milliseconds to the UTC date it falls on.
+fn convert_timestamp_millis_to_date(timestamp_millis: f64) -> Result<Date<Utc>, ProviderError> {
+    match Utc.timestamp_millis_opt(timestamp_millis as i64) {
+        chrono::offset::LocalResult::Single(date_time) => Ok(date_time.date()),
+        _ => Err(Box::from(format!(
+            "Timestamp is out of range: {}",
+            timestamp_millis
+        ))),
+    }
+}
+
+/// Converts a string in the format `yyyy-mm-dd` to a Date object.
+fn convert_date_string_to_date(date_string: &str) -> Result<Date<Utc>, ProviderError> {
+    let mut date_parts_iter = date_string.split('-');
+    let year = date_parts_iter.next().unwrap_or_default().parse::<i32>()?;
+    let month = date_parts_iter.next().unwrap_or_default().parse::<u32>()?;
+    let day = date_parts_iter.next().unwrap_or_default().parse::<u32>()?;
+
+    match Utc.ymd_opt(year, month, day) {
+        chrono::offset::LocalResult::Single(date) => Ok(date),
+        _ => Err(Box::from(format!(
+            "Cannot parse date string: {}",
+            date_string
+        ))),
+    }
+}
+
+#[derive(Deserialize)]
+struct BTCPriceCSVEntry {
+    /// Should always be in format "yyyy-mm-dd"
+    date: String,
+    open: String,
+    high: String,
+    low: String,
+    close: String,
+    volume: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bpi::btc_price_history::PriceBasis;
+    use crate::bpi::test_server::TestServer;
+
+    /// Two prices and one volume on 2023-09-01, and one price on 2023-09-02.
+    const COINGECKO_FIXTURE: &str = r#"{
+        "prices": [[1693526400000, 25900.5], [1693569600000, 26100.0], [1693612800000, 25800.25]],
+        "market_caps": [[1693526400000, 504000000000.0]],
+        "total_volumes": [[1693526400000, 12000000000.0]]
+    }"#;
+
+    const DATE_PRICE_LIST_FIXTURE: &str = r#"[
+        {"date": "2023-09-01", "price": 25900.5},
+        {"date": "2023-09-02", "price": 25800.25}
+    ]"#;
+
+    async fn load_from_test_server(
+        format: HttpJsonFormat,
+        status: u16,
+        body: &str,
+    ) -> (TestServer, Result<BTCPriceColumns, ProviderError>) {
+        let test_server = TestServer::start_with_response(status, body).await;
+        let provider =
+            HttpJsonProvider::new(test_server.get_url("/prices"), format, build_http_client());
+        let price_columns_result = provider.load_prices().await;
+        (test_server, price_columns_result)
+    }
+
+    #[rocket::async_test]
+    async fn loads_coingecko_market_chart() {
+        let (test_server, price_columns_result) =
+            load_from_test_server(HttpJsonFormat::CoinGecko, 200, COINGECKO_FIXTURE).await;
+        let price_columns = price_columns_result.unwrap();
+
+        let requests = test_server.get_requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].path, "/prices");
+        assert!(requests[0].body.is_empty());
+
+        let open = price_columns.get(PriceBasis::Open).unwrap();
+        let close = price_columns.get(PriceBasis::Close).unwrap();
+        assert_eq!(open.get_price(Utc.ymd(2023, 9, 1)), Some(25900.5));
+        assert_eq!(close.get_price(Utc.ymd(2023, 9, 1)), Some(26100.0));
+        assert_eq!(open.get_price(Utc.ymd(2023, 9, 2)), Some(25800.25));
+        assert_eq!(close.get_price(Utc.ymd(2023, 9, 2)), Some(25800.25));
+        assert!(price_columns.get(PriceBasis::High).is_none());
+    }
+
+    #[rocket::async_test]
+    async fn loads_date_price_list() {
+        let (_test_server, price_columns_result) =
+            load_from_test_server(HttpJsonFormat::DatePriceList, 200, DATE_PRICE_LIST_FIXTURE)
+                .await;
+        let price_columns = price_columns_result.unwrap();
+
+        for price_basis in [PriceBasis::Open, PriceBasis::Close] {
+            let series = price_columns.get(price_basis).unwrap();
+            assert_eq!(series.get_price(Utc.ymd(2023, 9, 1)), Some(25900.5));
+            assert_eq!(series.get_price(Utc.ymd(2023, 9, 2)), Some(25800.25));
+        }
+    }
+
+    #[rocket::async_test]
+    async fn fails_on_error_status() {
+        let (_test_server, price_columns_result) =
+            load_from_test_server(HttpJsonFormat::DatePriceList, 500, DATE_PRICE_LIST_FIXTURE)
+                .await;
+        assert!(price_columns_result.is_err());
+    }
+
+    #[rocket::async_test]
+    async fn fails_on_malformed_body() {
+        let (_test_server, price_columns_result) =
+            load_from_test_server(HttpJsonFormat::CoinGecko, 200, "{\"prices\": 5}").await;
+        assert!(price_columns_result.is_err());
+    }
+
+    #[rocket::async_test]
+    async fn fails_on_out_of_range_timestamp() {
+        let (_test_server, price_columns_result) = load_from_test_server(
+            HttpJsonFormat::CoinGecko,
+            200,
+            r#"{"prices": [[1693526400000, 25900.5], [1e300, 26000.0]]}"#,
+        )
+        .await;
+        let error = price_columns_result.err().unwrap();
+        assert!(error.to_string().contains("out of range"));
+    }
+
+    #[test]
+    fn skips_null_csv_values() {
+        let csv = "date,open,high,low,close,adj close,volume\n\
+                   2023-09-01,25900.5,26100.0,25700.0,26000.0,26000.0,12000000000\n\
+                   2023-09-02,null,null,null,null,null,null\n\
+                   2023-09-03,25800.0,null,25600.0,25900.0,25900.0,null\n";
+        let price_columns = parse_price_csv(csv.as_bytes()).unwrap();
+
+        let open = price_columns.get(PriceBasis::Open).unwrap();
+        let high = price_columns.get(PriceBasis::High).unwrap();
+        assert_eq!(open.get_price(Utc.ymd(2023, 9, 1)), Some(25900.5));
+        assert_eq!(open.get_price(Utc.ymd(2023, 9, 2)), None);
+        assert_eq!(open.get_price(Utc.ymd(2023, 9, 3)), Some(25800.0));
+        assert_eq!(high.get_price(Utc.ymd(2023, 9, 3)), None);
+        assert_eq!(high.get_last_entry_date(), Some(&Utc.ymd(2023, 9, 1)));
+    }
+
+    #[test]
+    fn parses_source_strings() {
+        assert!(HttpJsonProvider::from_source_string(
+            "coingecko=http://localhost/prices",
+            build_http_client()
+        )
+        .is_ok());
+        assert!(HttpJsonProvider::from_source_string(
+            "unknown=http://localhost/prices",
+            build_http_client()
+        )
+        .is_err());
+        assert!(HttpJsonProvider::from_source_string(
+            "http://localhost/prices",
+            build_http_client()
+        )
+        .is_err());
+    }
+}
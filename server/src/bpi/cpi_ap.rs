@@ -1,15 +1,33 @@
-use serde::Serialize;
+use super::units::PriceUnit;
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
 
-#[derive(Serialize, PartialEq, Eq, Clone, Hash)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Hash)]
 pub struct AreaCode(String);
 
+impl AreaCode {
+    pub(super) fn new(area_code: &str) -> Self {
+        Self(area_code.to_string())
+    }
+
+    /// The "U.S. city average" area, which covers the whole country.
+    pub fn us_city_average() -> Self {
+        Self::new("0000")
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
 impl<'a> rocket::form::FromFormField<'a> for AreaCode {
     fn from_value(field: rocket::form::ValueField<'a>) -> rocket::form::Result<'a, Self> {
         Ok(Self(String::from(field.value)))
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Area {
     area_code: AreaCode,
@@ -17,7 +35,7 @@ pub struct Area {
 }
 
 impl Area {
-    fn new_from_raw(raw_item: raw::RawArea) -> Self {
+    pub(super) fn new_from_raw(raw_item: raw::RawArea) -> Self {
         Self {
             area_code: AreaCode(raw_item.area_code),
             area_name: raw_item.area_name,
@@ -27,32 +45,56 @@ impl Area {
     pub fn get_area_code(&self) -> &AreaCode {
         &self.area_code
     }
+
+    pub fn get_area_name(&self) -> &str {
+        &self.area_name
+    }
 }
 
-pub fn get_areas() -> Result<Vec<Area>, reqwest::Error> {
-    raw::get_raw_areas().map(|raw_areas| raw_areas.into_iter().map(Area::new_from_raw).collect())
+pub fn get_areas(data_dir_or: Option<&Path>) -> Result<Vec<Area>, String> {
+    raw::get_raw_areas(data_dir_or)
+        .map(|raw_areas| raw_areas.into_iter().map(Area::new_from_raw).collect())
 }
 
-#[derive(Serialize, PartialEq, Eq, Clone, Hash)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Hash)]
 pub struct ItemCode(String);
 
+impl ItemCode {
+    pub(super) fn new(item_code: &str) -> Self {
+        Self(item_code.to_string())
+    }
+
+    /// The CPI-U "All items" index, i.e. headline inflation.
+    pub fn all_items() -> Self {
+        Self::new("SA0")
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
 impl<'a> rocket::form::FromFormField<'a> for ItemCode {
     fn from_value(field: rocket::form::ValueField<'a>) -> rocket::form::Result<'a, Self> {
         Ok(Self(String::from(field.value)))
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Item {
     item_code: ItemCode,
     item_name: String,
+    /// Parsed from the item name. `None` if the name doesn't say what quantity
+    /// the item is priced by.
+    price_unit: Option<PriceUnit>,
 }
 
 impl Item {
-    fn new_from_raw(raw_item: raw::RawItem) -> Self {
+    pub(super) fn new_from_raw(raw_item: raw::RawItem) -> Self {
         Self {
             item_code: ItemCode(raw_item.item_code),
+            price_unit: PriceUnit::from_item_name(&raw_item.item_name),
             item_name: raw_item.item_name,
         }
     }
@@ -60,10 +102,19 @@ impl Item {
     pub fn get_item_code(&self) -> &ItemCode {
         &self.item_code
     }
+
+    pub fn get_item_name(&self) -> &str {
+        &self.item_name
+    }
+
+    pub fn get_price_unit(&self) -> Option<&PriceUnit> {
+        self.price_unit.as_ref()
+    }
 }
 
-pub fn get_items() -> Result<Vec<Item>, reqwest::Error> {
-    raw::get_raw_items().map(|raw_items| raw_items.into_iter().map(Item::new_from_raw).collect())
+pub fn get_items(data_dir_or: Option<&Path>) -> Result<Vec<Item>, String> {
+    raw::get_raw_items(data_dir_or)
+        .map(|raw_items| raw_items.into_iter().map(Item::new_from_raw).collect())
 }
 
 pub struct SeriesEntry {
@@ -75,29 +126,79 @@ pub struct SeriesEntry {
 }
 
 impl SeriesEntry {
-    fn new_from_raw_or(raw_series_entry: raw::RawSeriesEntry) -> Option<Self> {
-        let area_code = raw_series_entry.get_area_code().to_string();
-        let item_code = raw_series_entry.get_item_code().to_string();
-        let month = raw_series_entry.get_period();
-
-        if !(1..=12).contains(&month) {
-            panic!("Month must be between 1 and 12! Got: {}", month);
+    pub(super) fn new(area_code: &str, item_code: &str, year: i32, month: u32, value: f64) -> Self {
+        Self {
+            area_code: AreaCode(area_code.to_string()),
+            item_code: ItemCode(item_code.to_string()),
+            year,
+            month,
+            value,
         }
+    }
+
+    /// Builds a series entry from the raw fields of a single BLS observation, in the
+    /// same format used by the `ap` flat files. Returns `None` if there's no value
+    /// or any of the fields are malformed.
+    pub fn new_from_parts_or(
+        series_id: &str,
+        year: &str,
+        period: &str,
+        value: &str,
+    ) -> Option<Self> {
+        Self::new_from_raw(raw::RawSeriesEntry {
+            series_id: series_id.to_string(),
+            year: year.to_string(),
+            period: period.to_string(),
+            value: value.to_string(),
+        })
+        .ok()
+        .flatten()
+    }
+
+    /// Returns `Ok(None)` if the entry has no value, and an error if any of
+    /// its fields are malformed.
+    fn new_from_raw(raw_series_entry: raw::RawSeriesEntry) -> Result<Option<Self>, String> {
+        let describe_entry = || {
+            format!(
+                "series {} in {} {}",
+                raw_series_entry.series_id, raw_series_entry.year, raw_series_entry.period
+            )
+        };
+
+        let (area_code, item_code) = raw_series_entry
+            .get_area_and_item_codes()
+            .ok_or_else(|| format!("Malformed series ID for {}", describe_entry()))?;
+        let month = match raw_series_entry.get_month() {
+            Some(month) => month,
+            None => {
+                return Err(format!(
+                    "Period must be between M01 and M12 for {}",
+                    describe_entry()
+                ))
+            }
+        };
 
         // A few of the CPI entries have a '-' for their value, indicating
         // that there is no data for that time period. We can simply filter
         // these out.
         if raw_series_entry.value == "-" {
-            return None;
+            return Ok(None);
         }
 
-        Some(Self {
-            area_code: AreaCode(area_code),
-            item_code: ItemCode(item_code),
-            year: raw_series_entry.year.parse::<i32>().unwrap(),
+        Ok(Some(Self {
+            area_code: AreaCode(area_code.to_string()),
+            item_code: ItemCode(item_code.to_string()),
+            year: parse_year_or(&raw_series_entry.year)
+                .ok_or_else(|| format!("Malformed year for {}", describe_entry()))?,
             month,
-            value: raw_series_entry.value.parse().unwrap(),
-        })
+            value: raw_series_entry.value.parse().map_err(|_| {
+                format!(
+                    "Malformed value '{}' for {}",
+                    raw_series_entry.value,
+                    describe_entry()
+                )
+            })?,
+        }))
     }
 
     pub fn get_area_code(&self) -> &AreaCode {
@@ -121,18 +222,43 @@ impl SeriesEntry {
     }
 }
 
-pub fn get_current_series_entries() -> Result<Vec<SeriesEntry>, reqwest::Error> {
-    raw::get_current_raw_series_entries().map(|raw_series_entries| {
-        raw_series_entries
-            .into_iter()
-            .filter_map(SeriesEntry::new_from_raw_or)
-            .collect()
-    })
+pub fn get_current_series_entries(data_dir_or: Option<&Path>) -> Result<Vec<SeriesEntry>, String> {
+    let mut series_entries = Vec::new();
+    for raw_series_entry in raw::get_current_raw_series_entries(data_dir_or)? {
+        if let Some(series_entry) = SeriesEntry::new_from_raw(raw_series_entry)? {
+            series_entries.push(series_entry);
+        }
+    }
+    Ok(series_entries)
 }
 
-mod raw {
+/// Parses a year, returning `None` unless it's a number that dates can be built from.
+pub(super) fn parse_year_or(year: &str) -> Option<i32> {
+    let year = year.parse::<i32>().ok()?;
+    Utc.ymd_opt(year, 1, 1).single().map(|_| year)
+}
+
+/// Names of the flat files that have embedded copies.
+const EMBEDDED_DATA_FILE_NAMES: &[&str] =
+    &["CPI-AREAS.txt", "CPI-ITEMS.txt", "CPI-TIME-SERIES.txt"];
+
+/// Returns the names of any flat files that are missing from `data_dir`, and so
+/// would be replaced by their embedded copies.
+pub fn get_missing_data_file_names(data_dir: &Path) -> Vec<&'static str> {
+    EMBEDDED_DATA_FILE_NAMES
+        .iter()
+        .filter(|file_name| !data_dir.join(file_name).is_file())
+        .cloned()
+        .collect()
+}
+
+/// Parsing for the tab-separated flat files published by the BLS. The `cu` index
+/// survey uses the same file format, so this is shared with `cpi_cu`.
+pub(super) mod raw {
     use serde::{de::value::MapDeserializer, Deserialize};
+    use std::borrow::Cow;
     use std::collections::HashMap;
+    use std::path::Path;
 
     #[derive(Deserialize)]
     pub struct RawArea {
@@ -140,8 +266,15 @@ mod raw {
         pub area_name: String,
     }
 
-    pub fn get_raw_areas() -> Result<Vec<RawArea>, reqwest::Error> {
-        get_data_sheet(include_str!("./CPI-AREAS.txt"))
+    pub fn get_raw_areas(data_dir_or: Option<&Path>) -> Result<Vec<RawArea>, String> {
+        get_data_sheet(
+            "CPI-AREAS.txt",
+            &read_data_file(
+                data_dir_or,
+                "CPI-AREAS.txt",
+                include_str!("./CPI-AREAS.txt"),
+            ),
+        )
     }
 
     #[derive(Deserialize)]
@@ -150,8 +283,15 @@ mod raw {
         pub item_name: String,
     }
 
-    pub fn get_raw_items() -> Result<Vec<RawItem>, reqwest::Error> {
-        get_data_sheet(include_str!("./CPI-ITEMS.txt"))
+    pub fn get_raw_items(data_dir_or: Option<&Path>) -> Result<Vec<RawItem>, String> {
+        get_data_sheet(
+            "CPI-ITEMS.txt",
+            &read_data_file(
+                data_dir_or,
+                "CPI-ITEMS.txt",
+                include_str!("./CPI-ITEMS.txt"),
+            ),
+        )
     }
 
     #[derive(Deserialize)]
@@ -166,24 +306,86 @@ mod raw {
     }
 
     impl RawSeriesEntry {
-        pub fn get_area_code(&self) -> &str {
-            &self.series_id[3..7]
+        /// Splits a series ID in the format `APU{area_code}{item_code}`. Returns
+        /// `None` if it's too short to contain both codes.
+        pub fn get_area_and_item_codes(&self) -> Option<(&str, &str)> {
+            if self.series_id.len() <= 7 || !self.series_id.is_ascii() {
+                return None;
+            }
+            Some((&self.series_id[3..7], &self.series_id[7..]))
         }
 
-        pub fn get_item_code(&self) -> &str {
-            &self.series_id[7..]
+        /// Returns the calendar month of monthly periods (`M01` to `M12`),
+        /// or `None` for any other period.
+        pub fn get_month(&self) -> Option<u32> {
+            let month = self.period.strip_prefix('M')?.parse::<u32>().ok()?;
+            if (1..=12).contains(&month) {
+                Some(month)
+            } else {
+                None
+            }
         }
+    }
+
+    pub fn get_current_raw_series_entries(
+        data_dir_or: Option<&Path>,
+    ) -> Result<Vec<RawSeriesEntry>, String> {
+        get_data_sheet(
+            "CPI-TIME-SERIES.txt",
+            &read_data_file(
+                data_dir_or,
+                "CPI-TIME-SERIES.txt",
+                include_str!("./CPI-TIME-SERIES.txt"),
+            ),
+        )
+    }
 
-        pub fn get_period(&self) -> u32 {
-            self.period[1..].parse().unwrap()
+    /// Reads `file_name` from the data directory if one is configured, falling
+    /// back to the copy embedded in the binary if the file can't be read.
+    fn read_data_file(
+        data_dir_or: Option<&Path>,
+        file_name: &str,
+        embedded_file_data: &'static str,
+    ) -> Cow<'static, str> {
+        let data_dir = match data_dir_or {
+            Some(data_dir) => data_dir,
+            None => return Cow::Borrowed(embedded_file_data),
+        };
+
+        let file_path = data_dir.join(file_name);
+        match std::fs::read_to_string(&file_path) {
+            Ok(file_data) => Cow::Owned(file_data),
+            Err(e) => {
+                eprintln!(
+                    "Failed to read {}, using embedded copy instead: {}",
+                    file_path.display(),
+                    e
+                );
+                Cow::Borrowed(embedded_file_data)
+            }
         }
     }
 
-    pub fn get_current_raw_series_entries() -> Result<Vec<RawSeriesEntry>, reqwest::Error> {
-        get_data_sheet(include_str!("./CPI-TIME-SERIES.txt"))
+    /// Reads `file_name` from the data directory, for files that have no embedded
+    /// copy. Returns `None` if there's no data directory or the file can't be read.
+    pub fn read_data_file_or(data_dir_or: Option<&Path>, file_name: &str) -> Option<String> {
+        let file_path = data_dir_or?.join(file_name);
+        match std::fs::read_to_string(&file_path) {
+            Ok(file_data) => Some(file_data),
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", file_path.display(), e);
+                None
+            }
+        }
     }
 
-    fn get_data_sheet<'de, T: Deserialize<'de>>(file_data: &str) -> Result<Vec<T>, reqwest::Error> {
+    /// Parses a tab-separated sheet whose first line holds the column names.
+    /// `file_name` is only used in error messages. Fails if any row has a different
+    /// number of columns than the header.
+    pub fn get_data_sheet<'de, T: Deserialize<'de>>(
+        file_name: &str,
+        file_data: &str,
+    ) -> Result<Vec<T>, String> {
         let mut line_iter = file_data.split('\n');
         let column_names: Vec<&str> = line_iter
             .next()
@@ -191,19 +393,27 @@ mod raw {
             .split('\t')
             .map(|segment| segment.trim())
             .collect();
-        let mut lines: Vec<&str> = line_iter.collect();
-        // Remove the last line, which is whitespace.
-        // TODO - See if removing this line breaks anything. It might not...?
-        lines.pop();
 
         let mut items = Vec::new();
 
-        for line in lines {
+        for (line_index, line) in line_iter.enumerate() {
+            // Skips the empty line after the final newline, if the file has one.
+            if line.trim().is_empty() {
+                continue;
+            }
+
             let mut item_map: HashMap<String, String> = HashMap::new();
 
             let column_values: Vec<&str> = line.split('\t').map(|segment| segment.trim()).collect();
             if column_names.len() != column_values.len() {
-                panic!("Data is inconsistent!");
+                return Err(format!(
+                    "{} line {}: expected {} columns, found {}",
+                    file_name,
+                    // Line numbers start at 1, and the header is the first line.
+                    line_index + 2,
+                    column_names.len(),
+                    column_values.len()
+                ));
             }
             for (column_name, column_value) in column_names.iter().zip(column_values) {
                 item_map.insert(column_name.to_string(), column_value.to_string());
@@ -226,3 +436,71 @@ mod raw {
         Ok(items)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIME_SERIES_HEADER: &str = "series_id\tyear\tperiod\tvalue\tfootnote_codes\n";
+
+    fn parse_time_series(rows: &str) -> Result<Vec<SeriesEntry>, String> {
+        let file_data = format!("{}{}", TIME_SERIES_HEADER, rows);
+        let mut series_entries = Vec::new();
+        for raw_series_entry in raw::get_data_sheet("CPI-TIME-SERIES.txt", &file_data)? {
+            if let Some(series_entry) = SeriesEntry::new_from_raw(raw_series_entry)? {
+                series_entries.push(series_entry);
+            }
+        }
+        Ok(series_entries)
+    }
+
+    #[test]
+    fn keeps_last_row_without_trailing_newline() {
+        let series_entries =
+            parse_time_series("APU0000701111\t2020\tM01\t0.50\t\nAPU0000701111\t2020\tM02\t0.52\t")
+                .unwrap();
+        assert_eq!(series_entries.len(), 2);
+        assert_eq!(series_entries[1].get_month(), 2);
+        assert_eq!(series_entries[1].get_value(), 0.52);
+    }
+
+    #[test]
+    fn skips_missing_values() {
+        let series_entries = parse_time_series(
+            "APU0000701111\t2020\tM01\t-\t\nAPU0000701111\t2020\tM02\t0.52\t\n\n",
+        )
+        .unwrap();
+        assert_eq!(series_entries.len(), 1);
+        assert_eq!(series_entries[0].get_area_code().as_str(), "0000");
+        assert_eq!(series_entries[0].get_item_code().as_str(), "701111");
+    }
+
+    #[test]
+    fn rejects_malformed_rows() {
+        let column_count_error =
+            parse_time_series("APU0000701111\t2020\tM01\t0.50\t\nAPU0000701111\t2020\n")
+                .err()
+                .unwrap();
+        assert_eq!(
+            column_count_error,
+            "CPI-TIME-SERIES.txt line 3: expected 5 columns, found 2"
+        );
+
+        assert!(parse_time_series("APU0000701111\t2020\tM13\t0.50\t\n")
+            .err()
+            .unwrap()
+            .contains("Period must be between M01 and M12"));
+        assert!(parse_time_series("APU0000701111\ttwenty\tM01\t0.50\t\n")
+            .err()
+            .unwrap()
+            .contains("Malformed year"));
+        assert!(parse_time_series("APU0000701111\t2020\tM01\tabc\t\n")
+            .err()
+            .unwrap()
+            .contains("Malformed value 'abc'"));
+        assert!(parse_time_series("APU0\t2020\tM01\t0.50\t\n")
+            .err()
+            .unwrap()
+            .contains("Malformed series ID"));
+    }
+}
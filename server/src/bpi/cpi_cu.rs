@@ -0,0 +1,74 @@
+use super::cpi_ap::{parse_year_or, raw, Area, Item, SeriesEntry};
+use serde::Deserialize;
+use std::path::Path;
+
+pub fn get_areas(data_dir_or: Option<&Path>) -> Result<Vec<Area>, String> {
+    Ok(get_data_sheet_or_empty(data_dir_or, "CU-AREAS.txt")?
+        .into_iter()
+        .map(Area::new_from_raw)
+        .collect())
+}
+
+pub fn get_items(data_dir_or: Option<&Path>) -> Result<Vec<Item>, String> {
+    Ok(get_data_sheet_or_empty(data_dir_or, "CU-ITEMS.txt")?
+        .into_iter()
+        .map(Item::new_from_raw)
+        .collect())
+}
+
+pub fn get_current_series_entries(data_dir_or: Option<&Path>) -> Result<Vec<SeriesEntry>, String> {
+    Ok(get_data_sheet_or_empty(data_dir_or, "CU-TIME-SERIES.txt")?
+        .into_iter()
+        .filter_map(new_series_entry_from_raw_or)
+        .collect())
+}
+
+#[derive(Deserialize)]
+struct RawIndexSeriesEntry {
+    /// In the format `CU{seasonal}{periodicity}{area_code}{item_code}`,
+    /// e.g. `CUUR0000SA0`.
+    series_id: String,
+    year: String,
+    /// In the format `Mxx` for monthly values. Some series also have
+    /// semiannual values, in the format `Sxx`.
+    period: String,
+    value: String,
+}
+
+/// Converts a raw `cu` entry to a series entry. Only monthly, seasonally unadjusted
+/// series are kept, so that there's exactly one series per item/area combo.
+fn new_series_entry_from_raw_or(raw_series_entry: RawIndexSeriesEntry) -> Option<SeriesEntry> {
+    let series_id = &raw_series_entry.series_id;
+    if !series_id.starts_with("CUUR") || series_id.len() <= 8 || !series_id.is_ascii() {
+        return None;
+    }
+
+    let month = raw_series_entry
+        .period
+        .strip_prefix('M')?
+        .parse::<u32>()
+        .ok()?;
+    if !(1..=12).contains(&month) {
+        return None;
+    }
+
+    Some(SeriesEntry::new(
+        &series_id[4..8],
+        &series_id[8..],
+        parse_year_or(&raw_series_entry.year)?,
+        month,
+        raw_series_entry.value.parse().ok()?,
+    ))
+}
+
+/// The `cu` flat files aren't embedded in the binary, so they're only
+/// loaded if they exist in the data directory.
+fn get_data_sheet_or_empty<T: for<'de> Deserialize<'de>>(
+    data_dir_or: Option<&Path>,
+    file_name: &str,
+) -> Result<Vec<T>, String> {
+    match raw::read_data_file_or(data_dir_or, file_name) {
+        Some(file_data) => raw::get_data_sheet(file_name, &file_data),
+        None => Ok(Vec::new()),
+    }
+}
@@ -1,78 +1,162 @@
 use super::cpi_ap::{
-    get_areas, get_current_series_entries, get_items, Area, AreaCode, Item, ItemCode,
+    get_areas, get_current_series_entries, get_items, get_missing_data_file_names, Area, AreaCode,
+    Item, ItemCode, SeriesEntry,
 };
+use super::cpi_cu;
 use super::dated_series::DatedSeries;
+use super::relative_importance::{self, ItemWeight};
 use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
 
+/// The BLS CPI surveys that a `CpiQueryEngine` can be built from.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub enum CpiDataset {
+    /// The `ap` survey. Series values are dollar prices of specific goods,
+    /// e.g. a pound of flour.
+    AveragePrice,
+    /// The `cu` (CPI-U) survey. Series values are index levels (e.g. 1982-84 = 100)
+    /// and use their own item codes, e.g. `SA0` for all items or `SAH1` for shelter.
+    Index,
+}
+
+/// Serializable so that it can be stored in engine snapshots.
+#[derive(Serialize, Deserialize)]
 pub struct CpiQueryEngine {
+    dataset: CpiDataset,
+    /// Directory that the CPI flat files were loaded from. If `None`, or if any file
+    /// was missing or malformed at startup, the embedded copies are used instead.
+    data_dir_or: Option<PathBuf>,
     areas: Vec<Area>,
     items: Vec<Item>,
     /// Contains all series entries, split by item code.
     /// Within each item code, all series entries are sorted chronologically.
     series_by_item_and_area_code: HashMap<(ItemCode, AreaCode), DatedSeries>,
+    /// Every series entry merged in by `with_series_entries`, e.g. from the BLS API.
+    /// Kept separately from the flat file data so that they survive reloads.
+    added_series_by_item_and_area_code: HashMap<(ItemCode, AreaCode), DatedSeries>,
+    /// BLS relative importance weights for each item. Only loaded
+    /// for the average price dataset.
+    item_weights: Vec<ItemWeight>,
 }
 
 impl CpiQueryEngine {
-    pub fn new() -> Self {
-        let mut series_entries_by_item_and_area_code = HashMap::new();
+    /// Fails if any of the CPI flat files are malformed.
+    pub fn new(dataset: CpiDataset, data_dir_or: Option<PathBuf>) -> Result<Self, String> {
+        let data_dir = data_dir_or.as_deref();
+        let (areas, items, series_entries, item_weights) = match dataset {
+            CpiDataset::AveragePrice => (
+                get_areas(data_dir)?,
+                get_items(data_dir)?,
+                get_current_series_entries(data_dir)?,
+                relative_importance::get_item_weights(data_dir)?,
+            ),
+            CpiDataset::Index => (
+                cpi_cu::get_areas(data_dir)?,
+                cpi_cu::get_items(data_dir)?,
+                cpi_cu::get_current_series_entries(data_dir)?,
+                Vec::new(),
+            ),
+        };
 
-        for series_entry in get_current_series_entries().unwrap() {
-            let item_and_area_code_key = (
-                series_entry.get_item_code().clone(),
-                series_entry.get_area_code().clone(),
-            );
+        Ok(Self {
+            dataset,
+            data_dir_or,
+            areas,
+            items,
+            series_by_item_and_area_code: build_series_map(series_entries),
+            added_series_by_item_and_area_code: HashMap::new(),
+            item_weights,
+        })
+    }
 
-            if !series_entries_by_item_and_area_code.contains_key(&item_and_area_code_key) {
-                series_entries_by_item_and_area_code.insert(
-                    (
-                        series_entry.get_item_code().clone(),
-                        series_entry.get_area_code().clone(),
-                    ),
-                    Vec::new(),
+    /// Same as `new`, but if the files in the data directory are malformed, logs why
+    /// and uses the embedded copies instead, the same as for missing files. The data
+    /// directory is still used for reloads, so that fixed files can be picked up.
+    pub fn new_or_embedded(dataset: CpiDataset, data_dir_or: Option<PathBuf>) -> Self {
+        match Self::new(dataset, data_dir_or.clone()) {
+            Ok(cpi_query_engine) => cpi_query_engine,
+            Err(e) => {
+                eprintln!(
+                    "Failed to load CPI data, using embedded copies instead: {}",
+                    e
                 );
+                let mut cpi_query_engine =
+                    Self::new(dataset, None).expect("Embedded CPI data is malformed");
+                cpi_query_engine.data_dir_or = data_dir_or;
+                cpi_query_engine
             }
+        }
+    }
 
-            // Note: unwrap is safe here because of the check above.
-            let entries_by_item_and_area_code = series_entries_by_item_and_area_code
-                .get_mut(&item_and_area_code_key)
-                .unwrap();
-            entries_by_item_and_area_code.push(series_entry);
+    /// Builds a new engine from the same data directory as this one, picking up
+    /// any changes to the CPI flat files. Unlike at startup, files missing from the
+    /// data directory are an error rather than replaced by their embedded copies.
+    /// Series entries added with `with_series_entries` are merged back in on top of
+    /// the reloaded files, the same as when they were first added.
+    pub fn reload(&self) -> Result<Self, String> {
+        let data_dir = match &self.data_dir_or {
+            Some(data_dir) => data_dir,
+            None => return Err("No CPI data directory is configured".to_string()),
+        };
+        if let CpiDataset::AveragePrice = self.dataset {
+            let missing_file_names = get_missing_data_file_names(data_dir);
+            if !missing_file_names.is_empty() {
+                return Err(format!(
+                    "Missing from {}: {}",
+                    data_dir.display(),
+                    missing_file_names.join(", ")
+                ));
+            }
         }
 
-        // Sort results for each item/area combo chronologically.
-        for (_, series_list) in series_entries_by_item_and_area_code.iter_mut() {
-            series_list.sort_by(|entry_a, entry_b| {
-                let entry_a_year = entry_a.get_year();
-                let entry_b_year = entry_b.get_year();
+        let mut cpi_query_engine = Self::new(self.dataset, self.data_dir_or.clone())?;
+        cpi_query_engine.series_by_item_and_area_code = merge_series_maps(
+            &cpi_query_engine.series_by_item_and_area_code,
+            &self.added_series_by_item_and_area_code,
+        );
+        cpi_query_engine.added_series_by_item_and_area_code =
+            self.added_series_by_item_and_area_code.clone();
+        Ok(cpi_query_engine)
+    }
 
-                if entry_a_year != entry_b_year {
-                    return entry_a_year.cmp(&entry_b_year);
-                }
+    /// Returns a copy of this engine with `series_entries` merged in. Wherever an
+    /// item/area combo already has a value for the same month, the new value wins.
+    pub fn with_series_entries(&self, series_entries: Vec<SeriesEntry>) -> Self {
+        let new_series_by_item_and_area_code = build_series_map(series_entries);
 
-                entry_a.get_month().cmp(&entry_b.get_month())
-            });
+        Self {
+            dataset: self.dataset,
+            data_dir_or: self.data_dir_or.clone(),
+            areas: self.areas.clone(),
+            items: self.items.clone(),
+            series_by_item_and_area_code: merge_series_maps(
+                &self.series_by_item_and_area_code,
+                &new_series_by_item_and_area_code,
+            ),
+            added_series_by_item_and_area_code: merge_series_maps(
+                &self.added_series_by_item_and_area_code,
+                &new_series_by_item_and_area_code,
+            ),
+            item_weights: self.item_weights.clone(),
         }
+    }
 
+    /// Builds an engine from just `series_entries`, with no areas, items or weights.
+    #[cfg(test)]
+    pub(super) fn from_series_entries(
+        dataset: CpiDataset,
+        series_entries: Vec<SeriesEntry>,
+    ) -> Self {
         Self {
-            areas: get_areas().unwrap(),
-            items: get_items().unwrap(),
-            series_by_item_and_area_code: series_entries_by_item_and_area_code
-                .into_iter()
-                .map(|(key, series_entries)| {
-                    let series_map = series_entries
-                        .into_iter()
-                        .map(|entry| {
-                            (
-                                Utc.ymd(entry.get_year(), entry.get_month(), 1), // TODO - Not sure if this should default to the 1st day of the month... Might make more sense to default to the middle of the month.
-                                entry.get_value(),
-                            )
-                        })
-                        .collect();
-
-                    (key, DatedSeries::new(series_map))
-                })
-                .collect(),
+            dataset,
+            data_dir_or: None,
+            areas: Vec::new(),
+            items: Vec::new(),
+            series_by_item_and_area_code: build_series_map(series_entries),
+            added_series_by_item_and_area_code: HashMap::new(),
+            item_weights: Vec::new(),
         }
     }
 
@@ -84,6 +168,14 @@ impl CpiQueryEngine {
         &self.items
     }
 
+    pub fn get_item_weights(&self) -> &Vec<ItemWeight> {
+        &self.item_weights
+    }
+
+    pub fn has_series_data(&self) -> bool {
+        !self.series_by_item_and_area_code.is_empty()
+    }
+
     pub fn get_series_data(
         &self,
         item_code: ItemCode,
@@ -93,3 +185,173 @@ impl CpiQueryEngine {
             .get(&(item_code, area_code))
     }
 }
+
+/// Merges each series in `newer_series_map` into the series with the same key in
+/// `series_map`. Wherever both have a value for the same date, the newer value wins.
+fn merge_series_maps(
+    series_map: &HashMap<(ItemCode, AreaCode), DatedSeries>,
+    newer_series_map: &HashMap<(ItemCode, AreaCode), DatedSeries>,
+) -> HashMap<(ItemCode, AreaCode), DatedSeries> {
+    let mut merged_series_map = series_map.clone();
+
+    for (key, newer_series) in newer_series_map {
+        let merged_series = match merged_series_map.get(key) {
+            Some(series) => series.merge(newer_series),
+            None => newer_series.clone(),
+        };
+        merged_series_map.insert(key.clone(), merged_series);
+    }
+
+    merged_series_map
+}
+
+/// Groups series entries by item/area combo, building a `DatedSeries` for each.
+fn build_series_map(
+    series_entries: Vec<SeriesEntry>,
+) -> HashMap<(ItemCode, AreaCode), DatedSeries> {
+    let mut series_entries_by_item_and_area_code = HashMap::new();
+
+    for series_entry in series_entries {
+        let item_and_area_code_key = (
+            series_entry.get_item_code().clone(),
+            series_entry.get_area_code().clone(),
+        );
+
+        if !series_entries_by_item_and_area_code.contains_key(&item_and_area_code_key) {
+            series_entries_by_item_and_area_code.insert(
+                (
+                    series_entry.get_item_code().clone(),
+                    series_entry.get_area_code().clone(),
+                ),
+                Vec::new(),
+            );
+        }
+
+        // Note: unwrap is safe here because of the check above.
+        let entries_by_item_and_area_code = series_entries_by_item_and_area_code
+            .get_mut(&item_and_area_code_key)
+            .unwrap();
+        entries_by_item_and_area_code.push(series_entry);
+    }
+
+    // Sort results for each item/area combo chronologically.
+    for (_, series_list) in series_entries_by_item_and_area_code.iter_mut() {
+        series_list.sort_by(|entry_a, entry_b| {
+            let entry_a_year = entry_a.get_year();
+            let entry_b_year = entry_b.get_year();
+
+            if entry_a_year != entry_b_year {
+                return entry_a_year.cmp(&entry_b_year);
+            }
+
+            entry_a.get_month().cmp(&entry_b.get_month())
+        });
+    }
+
+    series_entries_by_item_and_area_code
+        .into_iter()
+        .map(|(key, series_entries)| {
+            let series_map = series_entries
+                .into_iter()
+                .map(|entry| {
+                    (
+                        Utc.ymd(entry.get_year(), entry.get_month(), 1), // TODO - Not sure if this should default to the 1st day of the month... Might make more sense to default to the middle of the month.
+                        entry.get_value(),
+                    )
+                })
+                .collect();
+
+            (key, DatedSeries::new(series_map))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_data_dir(test_name: &str, time_series: &str) -> PathBuf {
+        let data_dir =
+            std::env::temp_dir().join(format!("satdash-{}-{}", test_name, std::process::id()));
+        std::fs::create_dir_all(&data_dir).unwrap();
+        std::fs::write(
+            data_dir.join("CPI-AREAS.txt"),
+            "area_code\tarea_name\n0000\tU.S. city average\n",
+        )
+        .unwrap();
+        std::fs::write(
+            data_dir.join("CPI-ITEMS.txt"),
+            "item_code\titem_name\n701111\tFlour, white, all purpose, per lb. (453.6 gm)\n",
+        )
+        .unwrap();
+        std::fs::write(
+            data_dir.join("CPI-TIME-SERIES.txt"),
+            format!("series_id\tyear\tperiod\tvalue\n{}", time_series),
+        )
+        .unwrap();
+        data_dir
+    }
+
+    fn get_flour_price(cpi_query_engine: &CpiQueryEngine, month: u32) -> Option<f64> {
+        cpi_query_engine
+            .get_series_data(ItemCode::new("701111"), AreaCode::new("0000"))?
+            .get_price(Utc.ymd(2023, month, 1))
+    }
+
+    #[test]
+    fn keeps_added_series_entries_across_reloads() {
+        let data_dir = write_data_dir(
+            "reload",
+            "APU0000701111\t2023\tM01\t0.50\nAPU0000701111\t2023\tM02\t0.51\n",
+        );
+
+        let cpi_query_engine =
+            CpiQueryEngine::new(CpiDataset::AveragePrice, Some(data_dir.clone()))
+                .unwrap()
+                .with_series_entries(vec![
+                    SeriesEntry::new("0000", "701111", 2023, 2, 0.55),
+                    SeriesEntry::new("0000", "701111", 2023, 3, 0.56),
+                ]);
+        assert_eq!(get_flour_price(&cpi_query_engine, 2), Some(0.55));
+
+        std::fs::write(
+            data_dir.join("CPI-TIME-SERIES.txt"),
+            "series_id\tyear\tperiod\tvalue\n\
+             APU0000701111\t2023\tM01\t0.60\n\
+             APU0000701111\t2023\tM02\t0.61\n\
+             APU0000701111\t2023\tM04\t0.62\n",
+        )
+        .unwrap();
+        let reloaded_cpi_query_engine = cpi_query_engine.reload().unwrap();
+        std::fs::remove_dir_all(&data_dir).unwrap();
+
+        assert_eq!(get_flour_price(&reloaded_cpi_query_engine, 1), Some(0.60));
+        assert_eq!(get_flour_price(&reloaded_cpi_query_engine, 2), Some(0.55));
+        assert_eq!(get_flour_price(&reloaded_cpi_query_engine, 3), Some(0.56));
+        assert_eq!(get_flour_price(&reloaded_cpi_query_engine, 4), Some(0.62));
+    }
+
+    #[test]
+    fn fails_to_reload_malformed_files() {
+        let data_dir = write_data_dir("malformed", "APU0000701111\t2023\tM01\t0.50\n");
+        let cpi_query_engine =
+            CpiQueryEngine::new(CpiDataset::AveragePrice, Some(data_dir.clone())).unwrap();
+
+        std::fs::write(
+            data_dir.join("CPI-TIME-SERIES.txt"),
+            "series_id\tyear\tperiod\tvalue\nAPU0000701111\t2023\tM01\n",
+        )
+        .unwrap();
+        let malformed_file_error = cpi_query_engine.reload().err().unwrap();
+
+        std::fs::remove_file(data_dir.join("CPI-TIME-SERIES.txt")).unwrap();
+        let missing_file_error = cpi_query_engine.reload().err().unwrap();
+        std::fs::remove_dir_all(&data_dir).unwrap();
+
+        assert_eq!(
+            malformed_file_error,
+            "CPI-TIME-SERIES.txt line 2: expected 4 columns, found 3"
+        );
+        assert!(missing_file_error.ends_with("CPI-TIME-SERIES.txt"));
+    }
+}
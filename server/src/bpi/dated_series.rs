@@ -1,7 +1,10 @@
-use chrono::{Date, Utc};
+use chrono::{Date, Datelike, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::ops::{Add, Div, Mul, Sub};
 
+#[derive(Clone)]
 pub struct DatedSeries {
     /// List of price points, sorted by date from earliest to latest.
     sorted_series_items: Vec<PricePoint>,
@@ -19,33 +22,325 @@ impl DatedSeries {
         }
     }
 
+    /// Builds a series from prices that are already sorted by date, with no
+    /// duplicate dates.
+    fn from_sorted_prices(sorted_prices: impl Iterator<Item = (Date<Utc>, f64)>) -> Self {
+        Self {
+            sorted_series_items: sorted_prices
+                .map(|(timestamp, price)| PricePoint { timestamp, price })
+                .collect(),
+        }
+    }
+
+    /// Combines this series with a newer one. Wherever both series have a price
+    /// for the same date, the price from `newer_series` is kept.
+    pub fn merge(&self, newer_series: &Self) -> Self {
+        let mut data: HashMap<Date<Utc>, f64> = self
+            .sorted_series_items
+            .iter()
+            .map(|price_point| (price_point.timestamp, price_point.price))
+            .collect();
+        for price_point in &newer_series.sorted_series_items {
+            data.insert(price_point.timestamp, price_point.price);
+        }
+        Self::new(data)
+    }
+
+    /// Gets the known price at exactly the specified date, without interpolating.
+    pub fn get_price(&self, date: Date<Utc>) -> Option<f64> {
+        match self.binary_search_sorted_price_point_vec_by_date(&date) {
+            BinarySearchResult::ExactResult(index) => Some(self.sorted_series_items[index].price),
+            _ => None,
+        }
+    }
+
+    /// Iterates over all known prices, sorted by date from earliest to latest.
+    pub fn iter(&self) -> impl Iterator<Item = (Date<Utc>, f64)> + '_ {
+        self.sorted_series_items
+            .iter()
+            .map(|price_point| (price_point.timestamp, price_point.price))
+    }
+
+    /// Iterates over the known prices between `start_or` and `end_or` (inclusive).
+    /// Either end of the range can be left open.
+    pub fn iter_between(
+        &self,
+        start_or: Option<Date<Utc>>,
+        end_or: Option<Date<Utc>>,
+    ) -> impl Iterator<Item = (Date<Utc>, f64)> + '_ {
+        let start_index = match start_or {
+            Some(start) => self
+                .sorted_series_items
+                .partition_point(|price_point| price_point.timestamp < start),
+            None => 0,
+        };
+        let end_index = match end_or {
+            Some(end) => self
+                .sorted_series_items
+                .partition_point(|price_point| price_point.timestamp <= end),
+            None => self.sorted_series_items.len(),
+        };
+
+        self.sorted_series_items[start_index..std::cmp::max(start_index, end_index)]
+            .iter()
+            .map(|price_point| (price_point.timestamp, price_point.price))
+    }
+
+    /// Estimates the price on each of `dates` using `strategy`, skipping any
+    /// dates that the series doesn't cover. `dates` must be sorted.
+    pub fn resample_at_dates(
+        &self,
+        dates: impl IntoIterator<Item = Date<Utc>>,
+        strategy: InterpolationStrategy,
+    ) -> Self {
+        Self::from_sorted_prices(dates.into_iter().filter_map(|date| {
+            Some((
+                date,
+                self.get_interpolated_price_with_strategy(date, strategy)?,
+            ))
+        }))
+    }
+
+    /// Estimates the price at every `interval` between `start` and `end`
+    /// (inclusive) using `strategy`. See `DateRange` for how dates are picked.
+    pub fn resample(
+        &self,
+        start: Date<Utc>,
+        end: Date<Utc>,
+        interval: InterpolationInterval,
+        strategy: InterpolationStrategy,
+    ) -> Self {
+        self.resample_at_dates(DateRange::new(start, end, interval), strategy)
+    }
+
+    /// Returns the dates at `interval` that both series cover, optionally narrowed
+    /// down to start and end overrides.
+    pub fn get_shared_dates(
+        &self,
+        other_series: &Self,
+        start_or: Option<Date<Utc>>,
+        end_or: Option<Date<Utc>>,
+        interval: InterpolationInterval,
+    ) -> Vec<Date<Utc>> {
+        let (mut start, mut end) = match (
+            self.get_first_shared_date(other_series),
+            self.get_last_shared_date(other_series),
+        ) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return Vec::new(),
+        };
+        if let Some(start_override) = start_or {
+            start = std::cmp::max(start, start_override);
+        }
+        if let Some(end_override) = end_or {
+            end = std::cmp::min(end, end_override);
+        }
+
+        DateRange::new(start, end, interval).collect()
+    }
+
+    /// Resamples both series onto the dates from `get_shared_dates`, so that they can
+    /// be combined elementwise. Each series is estimated using its own strategy.
+    #[allow(clippy::too_many_arguments)]
+    pub fn align(
+        &self,
+        other_series: &Self,
+        start_or: Option<Date<Utc>>,
+        end_or: Option<Date<Utc>>,
+        interval: InterpolationInterval,
+        strategy: InterpolationStrategy,
+        other_strategy: InterpolationStrategy,
+    ) -> (Self, Self) {
+        let dates = self.get_shared_dates(other_series, start_or, end_or, interval);
+        (
+            self.resample_at_dates(dates.iter().copied(), strategy),
+            other_series.resample_at_dates(dates, other_strategy),
+        )
+    }
+
+    /// Combines the prices of two series on every date that both have a known price
+    /// for. Dates where `combine` returns `None` are left out. Series with different
+    /// dates should be aligned first.
+    pub fn zip_with(&self, other_series: &Self, combine: impl Fn(f64, f64) -> Option<f64>) -> Self {
+        let mut sorted_prices = Vec::new();
+        let mut other_price_points = other_series.sorted_series_items.iter().peekable();
+        for price_point in &self.sorted_series_items {
+            // Skip past dates that only the other series has.
+            while other_price_points
+                .next_if(|other| other.timestamp < price_point.timestamp)
+                .is_some()
+            {}
+            if let Some(other_price_point) =
+                other_price_points.next_if(|other| other.timestamp == price_point.timestamp)
+            {
+                if let Some(price) = combine(price_point.price, other_price_point.price) {
+                    sorted_prices.push((price_point.timestamp, price));
+                }
+            }
+        }
+        Self::from_sorted_prices(sorted_prices.into_iter())
+    }
+
+    /// Applies `f` to every price, keeping the dates as they are.
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Self {
+        Self::from_sorted_prices(self.iter().map(|(date, price)| (date, f(price))))
+    }
+
+    /// Gets the mean of all known prices between `start` and `end` (inclusive),
+    /// without interpolating. Returns `None` if there are no prices in the range.
+    pub fn get_average_price(&self, start: Date<Utc>, end: Date<Utc>) -> Option<f64> {
+        let start_index = self
+            .sorted_series_items
+            .partition_point(|price_point| price_point.timestamp < start);
+        let end_index = self
+            .sorted_series_items
+            .partition_point(|price_point| price_point.timestamp <= end);
+        if start_index >= end_index {
+            return None;
+        }
+
+        let price_points = &self.sorted_series_items[start_index..end_index];
+        Some(
+            price_points
+                .iter()
+                .map(|price_point| price_point.price)
+                .sum::<f64>()
+                / price_points.len() as f64,
+        )
+    }
+
     /// Gets the estimated price at a particular instant. If we have a known price
     /// at the exact instant specified, we will return that value. Otherwise, we'll
     /// find the closest price before and after the specified instant and use linear
     /// interpolation to estimate the price.
     pub fn get_interpolated_price(&self, date: Date<Utc>) -> Option<f64> {
-        match self.binary_search_sorted_price_point_vec_by_date(&date) {
-            BinarySearchResult::ExactResult(data_point) => Some(data_point.price),
-            // Perform linear interpolation between closest upper and lower points.
-            BinarySearchResult::ClosestLowerAndUpperPoints(lower_data_point, upper_data_point) => {
-                let lower_time_diff = date - lower_data_point.timestamp;
-                let total_time_diff = upper_data_point.timestamp - lower_data_point.timestamp;
-                let total_price_diff = upper_data_point.price - lower_data_point.price;
-
-                let linear_slope = total_price_diff / (total_time_diff.num_milliseconds() as f64);
+        self.get_interpolated_price_with_strategy(date, InterpolationStrategy::Linear)
+    }
+
+    /// Same as `get_interpolated_price`, but estimates prices between known
+    /// points using the given strategy.
+    pub fn get_interpolated_price_with_strategy(
+        &self,
+        date: Date<Utc>,
+        strategy: InterpolationStrategy,
+    ) -> Option<f64> {
+        let (lower_index, upper_index) =
+            match self.binary_search_sorted_price_point_vec_by_date(&date) {
+                BinarySearchResult::ExactResult(index) => {
+                    return Some(self.sorted_series_items[index].price)
+                }
+                BinarySearchResult::ClosestLowerAndUpperPoints(lower_index, upper_index) => {
+                    (lower_index, upper_index)
+                }
+                BinarySearchResult::MissingData => return None,
+            };
+
+        let lower_data_point = &self.sorted_series_items[lower_index];
+        let upper_data_point = &self.sorted_series_items[upper_index];
+
+        let lower_time_diff = (date - lower_data_point.timestamp).num_milliseconds() as f64;
+        let total_time_diff =
+            (upper_data_point.timestamp - lower_data_point.timestamp).num_milliseconds() as f64;
+        // Fraction of the way from the lower point to the upper point.
+        let t = lower_time_diff / total_time_diff;
+
+        match strategy {
+            InterpolationStrategy::Linear => {
+                Some(lower_data_point.price + (upper_data_point.price - lower_data_point.price) * t)
+            }
+            InterpolationStrategy::Step => Some(lower_data_point.price),
+            InterpolationStrategy::LogLinear => {
+                // Logarithms are only defined for positive prices, so fall back to
+                // linear interpolation for anything else.
+                if lower_data_point.price <= 0.0 || upper_data_point.price <= 0.0 {
+                    return self
+                        .get_interpolated_price_with_strategy(date, InterpolationStrategy::Linear);
+                }
+                let lower_log_price = lower_data_point.price.ln();
+                let upper_log_price = upper_data_point.price.ln();
+                Some((lower_log_price + (upper_log_price - lower_log_price) * t).exp())
+            }
+            InterpolationStrategy::MonotoneCubic => {
+                // Cubic Hermite interpolation, using tangents that are
+                // guaranteed not to overshoot the known points.
+                let lower_tangent = self.get_monotone_tangent(lower_index);
+                let upper_tangent = self.get_monotone_tangent(upper_index);
+
+                let t2 = t * t;
+                let t3 = t2 * t;
                 Some(
-                    lower_data_point.price
-                        + ((lower_time_diff.num_milliseconds() as f64) * linear_slope),
+                    (2.0 * t3 - 3.0 * t2 + 1.0) * lower_data_point.price
+                        + (t3 - 2.0 * t2 + t) * total_time_diff * lower_tangent
+                        + (-2.0 * t3 + 3.0 * t2) * upper_data_point.price
+                        + (t3 - t2) * total_time_diff * upper_tangent,
                 )
             }
-            BinarySearchResult::MissingData => None,
         }
     }
 
-    fn binary_search_sorted_price_point_vec_by_date<'a>(
-        &'a self,
+    /// Returns the known points that `get_interpolated_price` estimates the price at
+    /// `date` from: just the point at `date` if there is one, or otherwise the closest
+    /// points before and after it. Empty if the series doesn't cover `date`.
+    pub fn get_interpolation_points(&self, date: Date<Utc>) -> Vec<(Date<Utc>, f64)> {
+        let indices = match self.binary_search_sorted_price_point_vec_by_date(&date) {
+            BinarySearchResult::ExactResult(index) => vec![index],
+            BinarySearchResult::ClosestLowerAndUpperPoints(lower_index, upper_index) => {
+                vec![lower_index, upper_index]
+            }
+            BinarySearchResult::MissingData => Vec::new(),
+        };
+
+        indices
+            .into_iter()
+            .map(|index| {
+                let price_point = &self.sorted_series_items[index];
+                (price_point.timestamp, price_point.price)
+            })
+            .collect()
+    }
+
+    /// Returns the slope (price per millisecond) of the line between two points.
+    fn get_secant_slope(&self, lower_index: usize, upper_index: usize) -> f64 {
+        let lower_data_point = &self.sorted_series_items[lower_index];
+        let upper_data_point = &self.sorted_series_items[upper_index];
+        (upper_data_point.price - lower_data_point.price)
+            / (upper_data_point.timestamp - lower_data_point.timestamp).num_milliseconds() as f64
+    }
+
+    /// Returns the tangent at a known point for monotone cubic interpolation,
+    /// using the Fritsch-Butland weighted harmonic mean of the neighboring secant
+    /// slopes. The tangent is zero at local extremes, which prevents overshoot.
+    fn get_monotone_tangent(&self, index: usize) -> f64 {
+        let last_index = self.sorted_series_items.len() - 1;
+        if index == 0 {
+            return self.get_secant_slope(0, 1);
+        }
+        if index == last_index {
+            return self.get_secant_slope(last_index - 1, last_index);
+        }
+
+        let lower_slope = self.get_secant_slope(index - 1, index);
+        let upper_slope = self.get_secant_slope(index, index + 1);
+        if lower_slope * upper_slope <= 0.0 {
+            return 0.0;
+        }
+
+        let lower_time_diff = (self.sorted_series_items[index].timestamp
+            - self.sorted_series_items[index - 1].timestamp)
+            .num_milliseconds() as f64;
+        let upper_time_diff = (self.sorted_series_items[index + 1].timestamp
+            - self.sorted_series_items[index].timestamp)
+            .num_milliseconds() as f64;
+
+        3.0 * (lower_time_diff + upper_time_diff)
+            / ((2.0 * upper_time_diff + lower_time_diff) / lower_slope
+                + (upper_time_diff + 2.0 * lower_time_diff) / upper_slope)
+    }
+
+    fn binary_search_sorted_price_point_vec_by_date(
+        &self,
         target_instant: &Date<Utc>,
-    ) -> BinarySearchResult<'a> {
+    ) -> BinarySearchResult {
         if self.sorted_series_items.is_empty() {
             return BinarySearchResult::MissingData;
         }
@@ -74,25 +369,17 @@ impl DatedSeries {
                     }
                     lower_bound = mid;
                 }
-                Ordering::Equal => return BinarySearchResult::ExactResult(mid_point),
+                Ordering::Equal => return BinarySearchResult::ExactResult(mid),
             };
         }
 
-        // If we've reached this far, `upper_bound` and `lower_bound` have crossed.
-        // This is why we're using them in what intuitively appears as the reverse.
-        let closest_lower_data_point = match self.sorted_series_items.get(upper_bound) {
-            Some(data_point) => data_point,
-            None => return BinarySearchResult::MissingData,
-        };
-        let closest_upper_data_point = match self.sorted_series_items.get(lower_bound) {
-            Some(data_point) => data_point,
-            None => return BinarySearchResult::MissingData,
-        };
+        // If we've reached this far, the target is between the points at `lower_bound`
+        // and `upper_bound`, which are next to each other.
+        if upper_bound >= self.sorted_series_items.len() {
+            return BinarySearchResult::MissingData;
+        }
 
-        BinarySearchResult::ClosestLowerAndUpperPoints(
-            closest_lower_data_point,
-            closest_upper_data_point,
-        )
+        BinarySearchResult::ClosestLowerAndUpperPoints(lower_bound, upper_bound)
     }
 
     pub fn get_first_entry_date(&self) -> Option<&Date<Utc>> {
@@ -122,13 +409,465 @@ impl DatedSeries {
     }
 }
 
+/// Serialized as a list of `(days since 0001-01-01, price)` pairs, since chrono
+/// dates aren't serializable without enabling its `serde` feature.
+impl Serialize for DatedSeries {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let day_prices: Vec<(i32, f64)> = self
+            .sorted_series_items
+            .iter()
+            .map(|price_point| (price_point.timestamp.num_days_from_ce(), price_point.price))
+            .collect();
+        day_prices.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DatedSeries {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let day_prices: Vec<(i32, f64)> = Vec::deserialize(deserializer)?;
+
+        // Series are serialized in order, so anything else means the data is corrupt.
+        if let Some(window) = day_prices
+            .windows(2)
+            .find(|window| window[0].0 >= window[1].0)
+        {
+            return Err(serde::de::Error::custom(format!(
+                "Day numbers are not strictly increasing: {} is followed by {}",
+                window[0].0, window[1].0
+            )));
+        }
+
+        let mut sorted_prices = Vec::with_capacity(day_prices.len());
+        for (days_from_ce, price) in day_prices {
+            let naive_date =
+                NaiveDate::from_num_days_from_ce_opt(days_from_ce).ok_or_else(|| {
+                    serde::de::Error::custom(format!("Invalid day number: {}", days_from_ce))
+                })?;
+            sorted_prices.push((Date::from_utc(naive_date, Utc), price));
+        }
+
+        Ok(Self::from_sorted_prices(sorted_prices.into_iter()))
+    }
+}
+
+// Elementwise arithmetic. Operations between two series only keep the dates that
+// both series have a known price for, so series with different dates should be
+// aligned first. Dividing by a series leaves out the dates where it's zero.
+
+impl Add for &DatedSeries {
+    type Output = DatedSeries;
+
+    fn add(self, other_series: Self) -> DatedSeries {
+        self.zip_with(other_series, |a, b| Some(a + b))
+    }
+}
+
+impl Sub for &DatedSeries {
+    type Output = DatedSeries;
+
+    fn sub(self, other_series: Self) -> DatedSeries {
+        self.zip_with(other_series, |a, b| Some(a - b))
+    }
+}
+
+impl Mul for &DatedSeries {
+    type Output = DatedSeries;
+
+    fn mul(self, other_series: Self) -> DatedSeries {
+        self.zip_with(other_series, |a, b| Some(a * b))
+    }
+}
+
+impl Div for &DatedSeries {
+    type Output = DatedSeries;
+
+    fn div(self, other_series: Self) -> DatedSeries {
+        self.zip_with(
+            other_series,
+            |a, b| if b == 0.0 { None } else { Some(a / b) },
+        )
+    }
+}
+
+impl Add<f64> for &DatedSeries {
+    type Output = DatedSeries;
+
+    fn add(self, value: f64) -> DatedSeries {
+        self.map(|price| price + value)
+    }
+}
+
+impl Sub<f64> for &DatedSeries {
+    type Output = DatedSeries;
+
+    fn sub(self, value: f64) -> DatedSeries {
+        self.map(|price| price - value)
+    }
+}
+
+impl Mul<f64> for &DatedSeries {
+    type Output = DatedSeries;
+
+    fn mul(self, value: f64) -> DatedSeries {
+        self.map(|price| price * value)
+    }
+}
+
+impl Div<f64> for &DatedSeries {
+    type Output = DatedSeries;
+
+    fn div(self, value: f64) -> DatedSeries {
+        self.map(|price| price / value)
+    }
+}
+
+/// Dates from `start` to `end` (inclusive), spaced `interval` apart.
+pub struct DateRange {
+    next_date: Date<Utc>,
+    end: Date<Utc>,
+    interval: InterpolationInterval,
+}
+
+impl DateRange {
+    pub fn new(start: Date<Utc>, end: Date<Utc>, interval: InterpolationInterval) -> Self {
+        Self {
+            next_date: start,
+            end,
+            interval,
+        }
+    }
+}
+
+impl Iterator for DateRange {
+    type Item = Date<Utc>;
+
+    fn next(&mut self) -> Option<Date<Utc>> {
+        let date = self.next_date;
+        if date > self.end {
+            return None;
+        }
+        self.next_date = match self.interval {
+            InterpolationInterval::Daily => date + chrono::Duration::days(1),
+            InterpolationInterval::Weekly => date + chrono::Duration::weeks(1),
+            // Monthly and yearly dates snap to the first day of the period, since
+            // that's where CPI data points are stored. Only the start date may fall
+            // in the middle of a period.
+            InterpolationInterval::Monthly => get_next_month_start(date),
+            InterpolationInterval::Yearly => Utc.ymd(date.year() + 1, 1, 1),
+        };
+        Some(date)
+    }
+}
+
+/// Returns the first day of the month after `date`.
+pub fn get_next_month_start(date: Date<Utc>) -> Date<Utc> {
+    if date.month() == 12 {
+        Utc.ymd(date.year() + 1, 1, 1)
+    } else {
+        Utc.ymd(date.year(), date.month() + 1, 1)
+    }
+}
+
+#[derive(Clone)]
 struct PricePoint {
     timestamp: Date<Utc>,
     price: f64,
 }
 
-enum BinarySearchResult<'a> {
-    ExactResult(&'a PricePoint),
-    ClosestLowerAndUpperPoints(&'a PricePoint, &'a PricePoint),
+/// Holds indices into `DatedSeries::sorted_series_items`.
+enum BinarySearchResult {
+    ExactResult(usize),
+    ClosestLowerAndUpperPoints(usize, usize),
     MissingData,
 }
+
+/// How to estimate a price between two known points.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InterpolationStrategy {
+    /// Straight line between the points.
+    Linear,
+    /// Last observation carried forward, for prices that change in steps.
+    Step,
+    /// Straight line between the logarithms of the points, for prices that
+    /// grow or shrink exponentially.
+    LogLinear,
+    /// Smooth curve through the points that never overshoots them.
+    MonotoneCubic,
+}
+
+impl<'a> rocket::form::FromFormField<'a> for InterpolationStrategy {
+    fn from_value(field: rocket::form::ValueField<'a>) -> rocket::form::Result<'a, Self> {
+        match field.value {
+            "linear" => Ok(Self::Linear),
+            "step" => Ok(Self::Step),
+            "log-linear" => Ok(Self::LogLinear),
+            "monotone-cubic" => Ok(Self::MonotoneCubic),
+            _ => Err(rocket::form::Error::validation(format!(
+                "Unknown interpolation strategy: {}",
+                field.value
+            ))
+            .into()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InterpolationInterval {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl<'a> rocket::form::FromFormField<'a> for InterpolationInterval {
+    fn from_value(field: rocket::form::ValueField<'a>) -> rocket::form::Result<'a, Self> {
+        match field.value {
+            "daily" => Ok(Self::Daily),
+            "weekly" => Ok(Self::Weekly),
+            "monthly" => Ok(Self::Monthly),
+            "yearly" => Ok(Self::Yearly),
+            _ => Err(rocket::form::Error::validation(format!(
+                "Unknown interpolation interval: {}",
+                field.value
+            ))
+            .into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_series(prices: &[((i32, u32, u32), f64)]) -> DatedSeries {
+        DatedSeries::new(
+            prices
+                .iter()
+                .map(|((year, month, day), price)| (Utc.ymd(*year, *month, *day), *price))
+                .collect(),
+        )
+    }
+
+    fn assert_close(value_or: Option<f64>, expected_value: f64) {
+        let value = value_or.unwrap();
+        assert!(
+            (value - expected_value).abs() < 1e-9,
+            "expected {}, got {}",
+            expected_value,
+            value
+        );
+    }
+
+    #[test]
+    fn interpolates_with_each_strategy() {
+        let series = get_series(&[((2023, 1, 1), 100.0), ((2023, 1, 11), 400.0)]);
+        let date = Utc.ymd(2023, 1, 6);
+
+        assert_close(
+            series.get_interpolated_price_with_strategy(date, InterpolationStrategy::Linear),
+            250.0,
+        );
+        assert_close(
+            series.get_interpolated_price_with_strategy(date, InterpolationStrategy::Step),
+            100.0,
+        );
+        // Halfway between 100 and 400 on a log scale.
+        assert_close(
+            series.get_interpolated_price_with_strategy(date, InterpolationStrategy::LogLinear),
+            200.0,
+        );
+        // With only two points, the tangents are the secant slope.
+        assert_close(
+            series.get_interpolated_price_with_strategy(date, InterpolationStrategy::MonotoneCubic),
+            250.0,
+        );
+    }
+
+    #[test]
+    fn returns_known_prices_and_nothing_outside_the_series() {
+        let series = get_series(&[((2023, 1, 1), 100.0), ((2023, 1, 11), 400.0)]);
+
+        for strategy in [
+            InterpolationStrategy::Linear,
+            InterpolationStrategy::Step,
+            InterpolationStrategy::LogLinear,
+            InterpolationStrategy::MonotoneCubic,
+        ] {
+            assert_close(
+                series.get_interpolated_price_with_strategy(Utc.ymd(2023, 1, 11), strategy),
+                400.0,
+            );
+            assert!(series
+                .get_interpolated_price_with_strategy(Utc.ymd(2022, 12, 31), strategy)
+                .is_none());
+            assert!(series
+                .get_interpolated_price_with_strategy(Utc.ymd(2023, 1, 12), strategy)
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn log_linear_falls_back_to_linear_for_non_positive_prices() {
+        let series = get_series(&[((2023, 1, 1), 0.0), ((2023, 1, 11), 100.0)]);
+        assert_close(
+            series.get_interpolated_price_with_strategy(
+                Utc.ymd(2023, 1, 6),
+                InterpolationStrategy::LogLinear,
+            ),
+            50.0,
+        );
+    }
+
+    #[test]
+    fn monotone_cubic_follows_straight_lines() {
+        let series = get_series(&[
+            ((2023, 1, 1), 0.0),
+            ((2023, 1, 11), 10.0),
+            ((2023, 1, 21), 20.0),
+            ((2023, 1, 31), 30.0),
+        ]);
+        for day in 1..=31 {
+            assert_close(
+                series.get_interpolated_price_with_strategy(
+                    Utc.ymd(2023, 1, day),
+                    InterpolationStrategy::MonotoneCubic,
+                ),
+                (day - 1) as f64,
+            );
+        }
+    }
+
+    #[test]
+    fn monotone_cubic_never_overshoots() {
+        let series = get_series(&[
+            ((2023, 1, 1), 10.0),
+            ((2023, 1, 11), 20.0),
+            ((2023, 1, 21), 20.0),
+            ((2023, 1, 31), 5.0),
+        ]);
+
+        let mut previous_price = 10.0;
+        for day in 2..=31 {
+            let price_or = series.get_interpolated_price_with_strategy(
+                Utc.ymd(2023, 1, day),
+                InterpolationStrategy::MonotoneCubic,
+            );
+            let price = price_or.unwrap();
+            // Allows for rounding errors, but nothing visible.
+            assert!(price > 5.0 - 1e-9 && price < 20.0 + 1e-9);
+            // Rises to the plateau, stays flat along it, then falls.
+            match day {
+                2..=11 => assert!(price > previous_price - 1e-9),
+                12..=21 => assert_close(price_or, 20.0),
+                _ => assert!(price < previous_price + 1e-9),
+            }
+            previous_price = price;
+        }
+    }
+
+    #[test]
+    fn round_trips_through_serialization() {
+        let series = get_series(&[((2023, 1, 1), 100.0), ((2023, 1, 11), 400.0)]);
+        let deserialized_series: DatedSeries =
+            bincode::deserialize(&bincode::serialize(&series).unwrap()).unwrap();
+        assert_eq!(
+            deserialized_series.iter().collect::<Vec<_>>(),
+            series.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn rejects_unsorted_or_duplicate_days() {
+        for day_prices in [vec![(2, 1.0), (1, 2.0)], vec![(1, 1.0), (1, 2.0)]] {
+            let error =
+                bincode::deserialize::<DatedSeries>(&bincode::serialize(&day_prices).unwrap())
+                    .err()
+                    .unwrap();
+            assert!(error
+                .to_string()
+                .starts_with("Day numbers are not strictly increasing"));
+        }
+    }
+
+    #[test]
+    fn zip_with_keeps_only_shared_dates() {
+        let series = get_series(&[
+            ((2023, 1, 1), 1.0),
+            ((2023, 1, 2), 2.0),
+            ((2023, 1, 4), 4.0),
+        ]);
+        let other_series = get_series(&[
+            ((2022, 12, 31), 10.0),
+            ((2023, 1, 2), 20.0),
+            ((2023, 1, 3), 30.0),
+            ((2023, 1, 4), 0.0),
+        ]);
+
+        assert_eq!(
+            (&series + &other_series).iter().collect::<Vec<_>>(),
+            vec![(Utc.ymd(2023, 1, 2), 22.0), (Utc.ymd(2023, 1, 4), 4.0)]
+        );
+        // Dividing by zero leaves the date out.
+        assert_eq!(
+            (&series / &other_series).iter().collect::<Vec<_>>(),
+            vec![(Utc.ymd(2023, 1, 2), 0.1)]
+        );
+        assert_eq!(
+            series
+                .zip_with(&other_series, |a, b| if a > 1.0 {
+                    Some(a * b)
+                } else {
+                    None
+                })
+                .iter()
+                .collect::<Vec<_>>(),
+            vec![(Utc.ymd(2023, 1, 2), 40.0), (Utc.ymd(2023, 1, 4), 0.0)]
+        );
+    }
+
+    #[test]
+    fn aligns_series_onto_shared_dates() {
+        let series = get_series(&[((2023, 1, 1), 0.0), ((2023, 1, 11), 10.0)]);
+        let other_series = get_series(&[((2023, 1, 5), 100.0), ((2023, 1, 15), 200.0)]);
+
+        let (aligned_series, aligned_other_series) = series.align(
+            &other_series,
+            None,
+            Some(Utc.ymd(2023, 1, 7)),
+            InterpolationInterval::Daily,
+            InterpolationStrategy::Linear,
+            InterpolationStrategy::Step,
+        );
+
+        let dates: Vec<Date<Utc>> = (5..=7).map(|day| Utc.ymd(2023, 1, day)).collect();
+        assert_eq!(
+            aligned_series.iter().collect::<Vec<_>>(),
+            dates
+                .iter()
+                .copied()
+                .zip([4.0, 5.0, 6.0])
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            aligned_other_series.iter().collect::<Vec<_>>(),
+            dates.iter().copied().zip([100.0; 3]).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn aligns_series_without_overlap_to_nothing() {
+        let series = get_series(&[((2023, 1, 1), 0.0), ((2023, 1, 11), 10.0)]);
+        let other_series = get_series(&[((2023, 2, 1), 100.0), ((2023, 2, 11), 200.0)]);
+
+        let (aligned_series, aligned_other_series) = series.align(
+            &other_series,
+            None,
+            None,
+            InterpolationInterval::Daily,
+            InterpolationStrategy::Linear,
+            InterpolationStrategy::Linear,
+        );
+        assert_eq!(aligned_series.iter().count(), 0);
+        assert_eq!(aligned_other_series.iter().count(), 0);
+    }
+}
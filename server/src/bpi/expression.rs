@@ -0,0 +1,565 @@
+use super::cpi_ap::{AreaCode, ItemCode};
+use super::dated_series::DatedSeries;
+use super::smoothing::{smooth_values, SmoothingMethod};
+use std::collections::HashMap;
+
+/// Longer expressions are rejected before parsing.
+const MAX_EXPRESSION_LENGTH: usize = 1000;
+/// Maximum nesting of parentheses, function calls and unary minus. The parser is
+/// recursive, so this keeps deeply-nested input from overflowing the stack.
+const MAX_NESTING_DEPTH: usize = 32;
+/// Smoothing window used when a smoothing function is called without one.
+const DEFAULT_SMOOTHING_WINDOW: usize = 30;
+
+/// A series that an expression reads from `BPIEngine` data.
+#[derive(PartialEq, Eq, Hash, Clone)]
+pub enum SeriesReference {
+    /// `ap(<item_code>, <area_code>)`: average price of an item in USD.
+    AveragePrice(ItemCode, AreaCode),
+    /// `cpi(<item_code>, <area_code>)`: CPI-U index value.
+    Index(ItemCode, AreaCode),
+    /// `btc`: BTC price in USD.
+    Btc,
+}
+
+#[derive(Clone, Copy)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+/// A parsed series expression, e.g. `sma(ap(701111, 0000) / btc * 1e8, 30)`.
+///
+/// Expressions support `+`, `-`, `*`, `/`, unary minus, parentheses, numbers, and
+/// these functions:
+/// * `ap(<item_code>, <area_code>)` and `cpi(<item_code>, <area_code>)`
+/// * `btc`
+/// * `basket(<area_code>, <item_code>[:<quantity>], ...)`, which is shorthand for
+///   adding up `ap(<item_code>, <area_code>) * <quantity>` for every item
+/// * `sma(<expression>[, <window>])`, `ema(...)` and `median(...)`, which smooth
+///   a series the same way as the `smoothing` query parameter
+pub enum Expression {
+    Number(f64),
+    Series(SeriesReference),
+    Negate(Box<Expression>),
+    BinaryOperation(Box<Expression>, Operator, Box<Expression>),
+    Smooth(Box<Expression>, SmoothingMethod, usize),
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        if text.len() > MAX_EXPRESSION_LENGTH {
+            return Err(format!(
+                "Expression is longer than {} characters",
+                MAX_EXPRESSION_LENGTH
+            ));
+        }
+
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+            depth: 0,
+        };
+        let expression = parser.parse_sum()?;
+        match parser.peek() {
+            None => Ok(expression),
+            Some(token) => Err(format!("Unexpected {}", token)),
+        }
+    }
+
+    /// Returns every series that the expression reads, without duplicates.
+    pub fn get_series_references(&self) -> Vec<&SeriesReference> {
+        let mut series_references = Vec::new();
+        self.collect_series_references(&mut series_references);
+        series_references
+    }
+
+    fn collect_series_references<'a>(&'a self, series_references: &mut Vec<&'a SeriesReference>) {
+        match self {
+            Self::Number(_) => {}
+            Self::Series(series_reference) => {
+                if !series_references.contains(&series_reference) {
+                    series_references.push(series_reference);
+                }
+            }
+            Self::Negate(operand) | Self::Smooth(operand, _, _) => {
+                operand.collect_series_references(series_references)
+            }
+            Self::BinaryOperation(left, _, right) => {
+                left.collect_series_references(series_references);
+                right.collect_series_references(series_references);
+            }
+        }
+    }
+
+    /// Evaluates the expression, given every series it references sampled on the
+    /// same dates. Dates where the result isn't a finite number are left out.
+    pub fn evaluate(
+        &self,
+        sampled_series: &HashMap<&SeriesReference, DatedSeries>,
+    ) -> Result<DatedSeries, String> {
+        match self.evaluate_value(sampled_series)? {
+            Value::Series(series) => Ok(DatedSeries::new(
+                series
+                    .iter()
+                    .filter(|(_, value)| value.is_finite())
+                    .collect(),
+            )),
+            Value::Number(_) => Err("Expression must include at least one series".to_string()),
+        }
+    }
+
+    fn evaluate_value(
+        &self,
+        sampled_series: &HashMap<&SeriesReference, DatedSeries>,
+    ) -> Result<Value, String> {
+        Ok(match self {
+            Self::Number(number) => Value::Number(*number),
+            Self::Series(series_reference) => match sampled_series.get(series_reference) {
+                Some(series) => Value::Series(series.clone()),
+                None => return Err("Series is missing from the evaluation".to_string()),
+            },
+            Self::Negate(operand) => match operand.evaluate_value(sampled_series)? {
+                Value::Number(number) => Value::Number(-number),
+                Value::Series(series) => Value::Series(series.map(|value| -value)),
+            },
+            Self::BinaryOperation(left, operator, right) => apply_operator(
+                left.evaluate_value(sampled_series)?,
+                *operator,
+                right.evaluate_value(sampled_series)?,
+            ),
+            Self::Smooth(operand, smoothing_method, window) => {
+                match operand.evaluate_value(sampled_series)? {
+                    Value::Number(number) => Value::Number(number),
+                    Value::Series(series) => {
+                        let (dates, values): (Vec<_>, Vec<_>) = series.iter().unzip();
+                        let smoothed_values = smooth_values(&values, *smoothing_method, *window);
+                        Value::Series(DatedSeries::new(
+                            dates.into_iter().zip(smoothed_values).collect(),
+                        ))
+                    }
+                }
+            }
+        })
+    }
+}
+
+enum Value {
+    Number(f64),
+    Series(DatedSeries),
+}
+
+fn apply_operator(left: Value, operator: Operator, right: Value) -> Value {
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => Value::Number(match operator {
+            Operator::Add => left + right,
+            Operator::Subtract => left - right,
+            Operator::Multiply => left * right,
+            Operator::Divide => left / right,
+        }),
+        (Value::Series(left), Value::Series(right)) => Value::Series(match operator {
+            Operator::Add => &left + &right,
+            Operator::Subtract => &left - &right,
+            Operator::Multiply => &left * &right,
+            Operator::Divide => &left / &right,
+        }),
+        (Value::Series(left), Value::Number(right)) => Value::Series(match operator {
+            Operator::Add => &left + right,
+            Operator::Subtract => &left - right,
+            Operator::Multiply => &left * right,
+            Operator::Divide => &left / right,
+        }),
+        (Value::Number(left), Value::Series(right)) => Value::Series(match operator {
+            Operator::Add => &right + left,
+            Operator::Subtract => right.map(|value| left - value),
+            Operator::Multiply => &right * left,
+            Operator::Divide => right.map(|value| left / value),
+        }),
+    }
+}
+
+#[derive(PartialEq)]
+enum Token {
+    /// A number, name or code. Codes like `0000` look like numbers, so whether
+    /// a word is a number depends on where it appears.
+    Word(String),
+    Symbol(char),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Word(word) => write!(f, "`{}`", word),
+            Self::Symbol(symbol) => write!(f, "`{}`", symbol),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if "+-*/(),:".contains(c) {
+            tokens.push(Token::Symbol(c));
+            continue;
+        }
+        if !is_word_char(c) {
+            return Err(format!("Unexpected character `{}`", c));
+        }
+
+        let mut word = c.to_string();
+        while let Some(next_c) = chars.peek().copied() {
+            // Keeps the sign of an exponent, e.g. `1e-8`, in the same word.
+            let is_exponent_sign = (next_c == '-' || next_c == '+')
+                && word.starts_with(|c: char| c.is_ascii_digit())
+                && (word.ends_with('e') || word.ends_with('E'));
+            if !is_word_char(next_c) && !is_exponent_sign {
+                break;
+            }
+            word.push(next_c);
+            chars.next();
+        }
+        tokens.push(Token::Word(word));
+    }
+
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+/// Recursive descent parser. Each `parse_*` method handles one precedence level.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next_token(&mut self) -> Result<&Token, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or_else(|| "Unexpected end of expression".to_string())?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    /// Consumes the next token if it's `symbol`.
+    fn next_if_symbol(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect_symbol(&mut self, symbol: char) -> Result<(), String> {
+        match self.next_token()? {
+            Token::Symbol(next_symbol) if *next_symbol == symbol => Ok(()),
+            token => Err(format!("Expected `{}` but found {}", symbol, token)),
+        }
+    }
+
+    fn expect_word(&mut self) -> Result<String, String> {
+        match self.next_token()? {
+            Token::Word(word) => Ok(word.clone()),
+            token => Err(format!("Expected a number or code but found {}", token)),
+        }
+    }
+
+    /// `<product> (('+' | '-') <product>)*`
+    fn parse_sum(&mut self) -> Result<Expression, String> {
+        let mut expression = self.parse_product()?;
+        loop {
+            let operator = if self.next_if_symbol('+') {
+                Operator::Add
+            } else if self.next_if_symbol('-') {
+                Operator::Subtract
+            } else {
+                return Ok(expression);
+            };
+            expression = Expression::BinaryOperation(
+                Box::from(expression),
+                operator,
+                Box::from(self.parse_product()?),
+            );
+        }
+    }
+
+    /// `<unary> (('*' | '/') <unary>)*`
+    fn parse_product(&mut self) -> Result<Expression, String> {
+        let mut expression = self.parse_unary()?;
+        loop {
+            let operator = if self.next_if_symbol('*') {
+                Operator::Multiply
+            } else if self.next_if_symbol('/') {
+                Operator::Divide
+            } else {
+                return Ok(expression);
+            };
+            expression = Expression::BinaryOperation(
+                Box::from(expression),
+                operator,
+                Box::from(self.parse_unary()?),
+            );
+        }
+    }
+
+    /// `'-' <unary> | <primary>`
+    fn parse_unary(&mut self) -> Result<Expression, String> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING_DEPTH {
+            return Err(format!(
+                "Expression is nested more than {} levels deep",
+                MAX_NESTING_DEPTH
+            ));
+        }
+
+        let expression = if self.next_if_symbol('-') {
+            Expression::Negate(Box::from(self.parse_unary()?))
+        } else {
+            self.parse_primary()?
+        };
+
+        self.depth -= 1;
+        Ok(expression)
+    }
+
+    /// `'(' <sum> ')' | <function call> | 'btc' | <number>`
+    fn parse_primary(&mut self) -> Result<Expression, String> {
+        if self.next_if_symbol('(') {
+            let expression = self.parse_sum()?;
+            self.expect_symbol(')')?;
+            return Ok(expression);
+        }
+
+        let word = self.expect_word()?;
+        if self.next_if_symbol('(') {
+            let expression = self.parse_function_arguments(&word)?;
+            self.expect_symbol(')')?;
+            return Ok(expression);
+        }
+
+        if word == "btc" {
+            return Ok(Expression::Series(SeriesReference::Btc));
+        }
+        parse_number(&word).map(Expression::Number)
+    }
+
+    /// Parses the arguments of a function call, up to but not including the `)`.
+    fn parse_function_arguments(&mut self, function_name: &str) -> Result<Expression, String> {
+        match function_name {
+            "ap" | "cpi" => {
+                let item_code = ItemCode::new(&self.expect_word()?);
+                self.expect_symbol(',')?;
+                let area_code = AreaCode::new(&self.expect_word()?);
+                Ok(Expression::Series(if function_name == "ap" {
+                    SeriesReference::AveragePrice(item_code, area_code)
+                } else {
+                    SeriesReference::Index(item_code, area_code)
+                }))
+            }
+            "basket" => {
+                let area_code = AreaCode::new(&self.expect_word()?);
+                let mut expression_or: Option<Expression> = None;
+                while self.next_if_symbol(',') {
+                    let item_code = ItemCode::new(&self.expect_word()?);
+                    let quantity = if self.next_if_symbol(':') {
+                        parse_number(&self.expect_word()?)?
+                    } else {
+                        1.0
+                    };
+
+                    let item_expression = Expression::BinaryOperation(
+                        Box::from(Expression::Series(SeriesReference::AveragePrice(
+                            item_code,
+                            area_code.clone(),
+                        ))),
+                        Operator::Multiply,
+                        Box::from(Expression::Number(quantity)),
+                    );
+                    expression_or = Some(match expression_or {
+                        Some(expression) => Expression::BinaryOperation(
+                            Box::from(expression),
+                            Operator::Add,
+                            Box::from(item_expression),
+                        ),
+                        None => item_expression,
+                    });
+                }
+                expression_or.ok_or_else(|| "`basket` needs at least one item".to_string())
+            }
+            "sma" | "ema" | "median" => {
+                let smoothing_method = match function_name {
+                    "sma" => SmoothingMethod::SimpleMovingAverage,
+                    "ema" => SmoothingMethod::ExponentialMovingAverage,
+                    _ => SmoothingMethod::CenteredMedian,
+                };
+                let operand = self.parse_sum()?;
+                let window = if self.next_if_symbol(',') {
+                    let window_word = self.expect_word()?;
+                    match window_word.parse::<usize>() {
+                        Ok(window) if window > 0 => window,
+                        _ => {
+                            return Err(format!(
+                                "Expected a positive whole number for the window but found `{}`",
+                                window_word
+                            ))
+                        }
+                    }
+                } else {
+                    DEFAULT_SMOOTHING_WINDOW
+                };
+                Ok(Expression::Smooth(
+                    Box::from(operand),
+                    smoothing_method,
+                    window,
+                ))
+            }
+            _ => Err(format!("Unknown function `{}`", function_name)),
+        }
+    }
+}
+
+fn parse_number(word: &str) -> Result<f64, String> {
+    match word.parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => Err(format!("Expected a number but found `{}`", word)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn evaluate_number(text: &str) -> f64 {
+        match Expression::parse(text)
+            .unwrap()
+            .evaluate_value(&HashMap::new())
+            .unwrap()
+        {
+            Value::Number(number) => number,
+            Value::Series(_) => panic!("`{}` evaluated to a series", text),
+        }
+    }
+
+    fn get_parse_error(text: &str) -> String {
+        Expression::parse(text).err().unwrap()
+    }
+
+    #[test]
+    fn applies_precedence_and_associativity() {
+        assert_eq!(evaluate_number("1 + 2 * 3"), 7.0);
+        assert_eq!(evaluate_number("(1 + 2) * 3"), 9.0);
+        assert_eq!(evaluate_number("10 - 2 - 3"), 5.0);
+        assert_eq!(evaluate_number("8 / 4 / 2"), 1.0);
+        assert_eq!(evaluate_number("2 * 3 - 8 / 2"), 2.0);
+    }
+
+    #[test]
+    fn applies_unary_minus() {
+        assert_eq!(evaluate_number("-2 * 3"), -6.0);
+        assert_eq!(evaluate_number("--2"), 2.0);
+        assert_eq!(evaluate_number("2 - -1"), 3.0);
+        assert_eq!(evaluate_number("-(1 + 2)"), -3.0);
+    }
+
+    #[test]
+    fn keeps_exponent_signs_in_numbers() {
+        assert!(
+            tokenize("1e-8+2E+3").unwrap()
+                == vec![
+                    Token::Word("1e-8".to_string()),
+                    Token::Symbol('+'),
+                    Token::Word("2E+3".to_string()),
+                ]
+        );
+        assert_eq!(evaluate_number("1e-8*2"), 2e-8);
+        assert_eq!(evaluate_number("1e+2-1"), 99.0);
+        // Only numbers have exponents, so this is a subtraction.
+        assert!(
+            tokenize("e-8").unwrap()
+                == vec![
+                    Token::Word("e".to_string()),
+                    Token::Symbol('-'),
+                    Token::Word("8".to_string()),
+                ]
+        );
+    }
+
+    #[test]
+    fn expands_baskets() {
+        let expression = Expression::parse("basket(0000, 701111:2, 708111)").unwrap();
+        let flour = SeriesReference::AveragePrice(ItemCode::new("701111"), AreaCode::new("0000"));
+        let eggs = SeriesReference::AveragePrice(ItemCode::new("708111"), AreaCode::new("0000"));
+        assert!(expression.get_series_references() == vec![&flour, &eggs]);
+
+        let date = Utc.ymd(2023, 1, 1);
+        let sampled_series = HashMap::from([
+            (&flour, DatedSeries::new(HashMap::from([(date, 1.5)]))),
+            (&eggs, DatedSeries::new(HashMap::from([(date, 4.0)]))),
+        ]);
+        let series = expression.evaluate(&sampled_series).unwrap();
+        assert_eq!(series.iter().collect::<Vec<_>>(), vec![(date, 7.0)]);
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(evaluate_number(&nested(MAX_NESTING_DEPTH - 1)), 1.0);
+        assert_eq!(
+            get_parse_error(&nested(MAX_NESTING_DEPTH)),
+            "Expression is nested more than 32 levels deep"
+        );
+        assert_eq!(
+            get_parse_error(&format!("{}1", "-".repeat(MAX_NESTING_DEPTH))),
+            "Expression is nested more than 32 levels deep"
+        );
+    }
+
+    #[test]
+    fn explains_errors() {
+        assert_eq!(get_parse_error("1 +"), "Unexpected end of expression");
+        assert_eq!(get_parse_error("1 2"), "Unexpected `2`");
+        assert_eq!(get_parse_error("1 $ 2"), "Unexpected character `$`");
+        assert_eq!(get_parse_error("foo(1)"), "Unknown function `foo`");
+        assert_eq!(get_parse_error("bar"), "Expected a number but found `bar`");
+        assert_eq!(
+            get_parse_error("ap(701111 0000)"),
+            "Expected `,` but found `0000`"
+        );
+        assert_eq!(
+            get_parse_error("basket(0000)"),
+            "`basket` needs at least one item"
+        );
+        assert_eq!(
+            get_parse_error("sma(btc, 0)"),
+            "Expected a positive whole number for the window but found `0`"
+        );
+        assert_eq!(
+            get_parse_error(&"1".repeat(MAX_EXPRESSION_LENGTH + 1)),
+            "Expression is longer than 1000 characters"
+        );
+        assert_eq!(
+            Expression::parse("1 + 2")
+                .unwrap()
+                .evaluate(&HashMap::new())
+                .err()
+                .unwrap(),
+            "Expression must include at least one series"
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use super::cpi_ap::{Item, ItemCode};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// A group of average price items, e.g. dairy or motor fuel. Categories can be
+/// nested, in which case items are only stored in the innermost category.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemCategory {
+    category_code: String,
+    category_name: String,
+    subcategories: Vec<ItemCategory>,
+    items: Vec<Item>,
+}
+
+impl ItemCategory {
+    /// Returns the codes of every item in this category and its subcategories.
+    pub fn get_all_item_codes(&self) -> Vec<ItemCode> {
+        let mut item_codes: Vec<ItemCode> = self
+            .items
+            .iter()
+            .map(|item| item.get_item_code().clone())
+            .collect();
+        for subcategory in &self.subcategories {
+            item_codes.extend(subcategory.get_all_item_codes());
+        }
+        item_codes
+    }
+}
+
+struct CategoryDefinition {
+    category_code: &'static str,
+    category_name: &'static str,
+    /// Items whose codes start with any of these prefixes belong to this category.
+    item_code_prefixes: &'static [&'static str],
+    subcategories: &'static [CategoryDefinition],
+}
+
+/// Categories loosely follow the BLS expenditure groups. Item codes starting with
+/// `7` are grouped by their first three digits, while the newer `F` item codes are
+/// grouped by their first two characters.
+const CATEGORY_DEFINITIONS: &[CategoryDefinition] = &[
+    CategoryDefinition {
+        category_code: "food",
+        category_name: "Food",
+        item_code_prefixes: &[],
+        subcategories: &[
+            CategoryDefinition {
+                category_code: "cereals-and-bakery",
+                category_name: "Cereals and bakery products",
+                item_code_prefixes: &["701", "702"],
+                subcategories: &[],
+            },
+            CategoryDefinition {
+                category_code: "meats-poultry-fish-and-eggs",
+                category_name: "Meats, poultry, fish, and eggs",
+                item_code_prefixes: &[],
+                subcategories: &[
+                    CategoryDefinition {
+                        category_code: "beef",
+                        category_name: "Beef",
+                        item_code_prefixes: &["703", "FC"],
+                        subcategories: &[],
+                    },
+                    CategoryDefinition {
+                        category_code: "pork",
+                        category_name: "Pork",
+                        item_code_prefixes: &["704", "FD"],
+                        subcategories: &[],
+                    },
+                    CategoryDefinition {
+                        category_code: "other-meats",
+                        category_name: "Other meats",
+                        item_code_prefixes: &["705"],
+                        subcategories: &[],
+                    },
+                    CategoryDefinition {
+                        category_code: "poultry",
+                        category_name: "Poultry",
+                        item_code_prefixes: &["706", "FF"],
+                        subcategories: &[],
+                    },
+                    CategoryDefinition {
+                        category_code: "fish-and-seafood",
+                        category_name: "Fish and seafood",
+                        item_code_prefixes: &["707"],
+                        subcategories: &[],
+                    },
+                    CategoryDefinition {
+                        category_code: "eggs",
+                        category_name: "Eggs",
+                        item_code_prefixes: &["708"],
+                        subcategories: &[],
+                    },
+                ],
+            },
+            CategoryDefinition {
+                category_code: "dairy",
+                category_name: "Dairy and related products",
+                item_code_prefixes: &["709", "710", "FJ", "FS"],
+                subcategories: &[],
+            },
+            CategoryDefinition {
+                category_code: "fruits-and-vegetables",
+                category_name: "Fruits and vegetables",
+                item_code_prefixes: &["711", "712", "713", "714", "FL"],
+                subcategories: &[],
+            },
+            CategoryDefinition {
+                category_code: "sugar-and-sweets",
+                category_name: "Sugar and sweets",
+                item_code_prefixes: &["715"],
+                subcategories: &[],
+            },
+            CategoryDefinition {
+                category_code: "fats-and-oils",
+                category_name: "Fats and oils",
+                item_code_prefixes: &["716"],
+                subcategories: &[],
+            },
+            CategoryDefinition {
+                category_code: "nonalcoholic-beverages",
+                category_name: "Nonalcoholic beverages",
+                item_code_prefixes: &["717", "FN"],
+                subcategories: &[],
+            },
+            CategoryDefinition {
+                category_code: "other-foods",
+                category_name: "Other foods",
+                item_code_prefixes: &["718"],
+                subcategories: &[],
+            },
+        ],
+    },
+    CategoryDefinition {
+        category_code: "alcoholic-beverages",
+        category_name: "Alcoholic beverages",
+        item_code_prefixes: &["720"],
+        subcategories: &[],
+    },
+    CategoryDefinition {
+        category_code: "energy",
+        category_name: "Energy",
+        item_code_prefixes: &[],
+        subcategories: &[
+            CategoryDefinition {
+                category_code: "household-energy",
+                category_name: "Household energy",
+                item_code_prefixes: &["725", "726"],
+                subcategories: &[],
+            },
+            CategoryDefinition {
+                category_code: "motor-fuel",
+                category_name: "Motor fuel",
+                item_code_prefixes: &["747"],
+                subcategories: &[],
+            },
+        ],
+    },
+];
+
+/// Sorts `items` into the category tree. Each item goes in the category with the
+/// longest matching prefix. Items that don't match any category are put in a
+/// catch-all `other` category, and empty categories are left out.
+pub fn build_category_tree(items: &[Item]) -> Vec<ItemCategory> {
+    let mut items_by_category_code: HashMap<&str, Vec<Item>> = HashMap::new();
+    let mut uncategorized_items = Vec::new();
+    for item in items {
+        match find_matching_category_code_or(CATEGORY_DEFINITIONS, item.get_item_code().as_str()) {
+            Some((_, category_code)) => items_by_category_code
+                .entry(category_code)
+                .or_default()
+                .push(item.clone()),
+            None => uncategorized_items.push(item.clone()),
+        }
+    }
+
+    let mut categories = build_categories(CATEGORY_DEFINITIONS, &mut items_by_category_code);
+    if !uncategorized_items.is_empty() {
+        categories.push(ItemCategory {
+            category_code: "other".to_string(),
+            category_name: "Other".to_string(),
+            subcategories: Vec::new(),
+            items: uncategorized_items,
+        });
+    }
+
+    categories
+}
+
+/// Searches a category tree, including nested subcategories, by category code.
+pub fn find_category<'a>(
+    categories: &'a [ItemCategory],
+    category_code: &str,
+) -> Option<&'a ItemCategory> {
+    for category in categories {
+        if category.category_code == category_code {
+            return Some(category);
+        }
+        if let Some(subcategory) = find_category(&category.subcategories, category_code) {
+            return Some(subcategory);
+        }
+    }
+    None
+}
+
+/// Returns the code of the category whose prefix matches the most characters
+/// of `item_code`, along with the length of the matching prefix.
+fn find_matching_category_code_or(
+    category_definitions: &'static [CategoryDefinition],
+    item_code: &str,
+) -> Option<(usize, &'static str)> {
+    let mut best_match_or: Option<(usize, &'static str)> = None;
+
+    for category_definition in category_definitions {
+        let own_match_or = category_definition
+            .item_code_prefixes
+            .iter()
+            .filter(|prefix| item_code.starts_with(*prefix))
+            .map(|prefix| (prefix.len(), category_definition.category_code))
+            .max();
+        let subcategory_match_or =
+            find_matching_category_code_or(category_definition.subcategories, item_code);
+
+        for (prefix_len, category_code) in [own_match_or, subcategory_match_or]
+            .iter()
+            .flatten()
+            .copied()
+        {
+            let is_better_match = match best_match_or {
+                Some((best_prefix_len, _)) => prefix_len > best_prefix_len,
+                None => true,
+            };
+            if is_better_match {
+                best_match_or = Some((prefix_len, category_code));
+            }
+        }
+    }
+
+    best_match_or
+}
+
+fn build_categories(
+    category_definitions: &[CategoryDefinition],
+    items_by_category_code: &mut HashMap<&str, Vec<Item>>,
+) -> Vec<ItemCategory> {
+    category_definitions
+        .iter()
+        .map(|category_definition| ItemCategory {
+            category_code: category_definition.category_code.to_string(),
+            category_name: category_definition.category_name.to_string(),
+            subcategories: build_categories(
+                category_definition.subcategories,
+                items_by_category_code,
+            ),
+            items: items_by_category_code
+                .remove(category_definition.category_code)
+                .unwrap_or_default(),
+        })
+        .filter(|category| !category.items.is_empty() || !category.subcategories.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bpi::cpi_ap::raw::RawItem;
+
+    fn get_items(item_codes: &[&str]) -> Vec<Item> {
+        item_codes
+            .iter()
+            .map(|item_code| {
+                Item::new_from_raw(RawItem {
+                    item_code: item_code.to_string(),
+                    item_name: format!("Item {}", item_code),
+                })
+            })
+            .collect()
+    }
+
+    fn get_category_codes(categories: &[ItemCategory]) -> Vec<&str> {
+        categories
+            .iter()
+            .map(|category| category.category_code.as_str())
+            .collect()
+    }
+
+    fn get_item_codes(item_codes: Vec<ItemCode>) -> Vec<String> {
+        item_codes
+            .iter()
+            .map(|item_code| item_code.as_str().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn prefers_the_longest_matching_prefix() {
+        const NESTED_DEFINITIONS: &[CategoryDefinition] = &[CategoryDefinition {
+            category_code: "parent",
+            category_name: "Parent",
+            item_code_prefixes: &["70"],
+            subcategories: &[CategoryDefinition {
+                category_code: "child",
+                category_name: "Child",
+                item_code_prefixes: &["701"],
+                subcategories: &[],
+            }],
+        }];
+
+        assert_eq!(
+            find_matching_category_code_or(NESTED_DEFINITIONS, "701111"),
+            Some((3, "child"))
+        );
+        assert_eq!(
+            find_matching_category_code_or(NESTED_DEFINITIONS, "702111"),
+            Some((2, "parent"))
+        );
+        assert_eq!(
+            find_matching_category_code_or(NESTED_DEFINITIONS, "801111"),
+            None
+        );
+    }
+
+    #[test]
+    fn sorts_items_into_the_innermost_category() {
+        let categories = build_category_tree(&get_items(&["701111", "708111", "FC1101", "ZZ999"]));
+
+        // Empty categories like energy are left out, and unmatched items go last.
+        assert_eq!(get_category_codes(&categories), vec!["food", "other"]);
+        let food = &categories[0];
+        assert!(food.items.is_empty());
+        assert_eq!(
+            get_category_codes(&food.subcategories),
+            vec!["cereals-and-bakery", "meats-poultry-fish-and-eggs"]
+        );
+        assert_eq!(
+            get_category_codes(&food.subcategories[1].subcategories),
+            vec!["beef", "eggs"]
+        );
+        assert_eq!(
+            get_item_codes(categories[1].get_all_item_codes()),
+            vec!["ZZ999"]
+        );
+    }
+
+    #[test]
+    fn finds_nested_categories_and_their_items() {
+        let categories = build_category_tree(&get_items(&["701111", "708111", "FC1101", "ZZ999"]));
+
+        let food = find_category(&categories, "food").unwrap();
+        assert_eq!(
+            get_item_codes(food.get_all_item_codes()),
+            vec!["701111", "FC1101", "708111"]
+        );
+        let eggs = find_category(&categories, "eggs").unwrap();
+        assert_eq!(get_item_codes(eggs.get_all_item_codes()), vec!["708111"]);
+        assert!(find_category(&categories, "energy").is_none());
+    }
+}
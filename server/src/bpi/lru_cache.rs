@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// A fixed-capacity cache that evicts the least recently used entry when full.
+/// Lookups are O(1), but eviction scans every entry. That's cheap at the capacities
+/// used here compared to computing a new entry.
+pub struct LruCache<K, V> {
+    capacity: usize,
+    /// Maps each key to its value and the tick it was last used at.
+    entries: HashMap<K, (V, u64)>,
+    /// Incremented on every access, so that higher ticks are more recently used.
+    current_tick: u64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            current_tick: 0,
+        }
+    }
+
+    /// Returns a copy of the value for `key`, marking it as recently used.
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        self.current_tick += 1;
+        let (value, last_used_tick) = self.entries.get_mut(key)?;
+        *last_used_tick = self.current_tick;
+        Some(value.clone())
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let least_recently_used_key_or = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used_tick))| *last_used_tick)
+                .map(|(key, _)| key.clone());
+            if let Some(least_recently_used_key) = least_recently_used_key_or {
+                self.entries.remove(&least_recently_used_key);
+            }
+        }
+
+        self.current_tick += 1;
+        self.entries.insert(key, (value, self.current_tick));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_keys(cache: &mut LruCache<&'static str, u32>) -> Vec<&'static str> {
+        ["a", "b", "c", "d"]
+            .iter()
+            .copied()
+            .filter(|key| cache.get(key).is_some())
+            .collect()
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("c", 3);
+
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some(2));
+        assert_eq!(cache.get(&"c"), Some(3));
+    }
+
+    #[test]
+    fn get_marks_entries_as_recently_used() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get(&"a"), Some(1));
+        cache.insert("c", 3);
+
+        assert_eq!(get_keys(&mut cache), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn replaces_existing_keys_without_evicting() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("a", 10);
+
+        assert_eq!(cache.get(&"a"), Some(10));
+        assert_eq!(cache.get(&"b"), Some(2));
+
+        // Replacing "a" also made it the most recently used.
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("a", 10);
+        cache.insert("c", 3);
+        assert_eq!(get_keys(&mut cache), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn stores_nothing_with_zero_capacity() {
+        let mut cache = LruCache::new(0);
+        cache.insert("a", 1);
+        assert_eq!(cache.get(&"a"), None);
+    }
+}
@@ -1,26 +1,334 @@
+mod bls_api;
 mod btc_price_history;
+mod btc_price_provider;
 mod cpi_ap;
+mod cpi_cu;
 mod cpi_query_engine;
 mod dated_series;
+mod expression;
+mod item_categories;
+mod lru_cache;
+mod regional_comparison;
+mod relative_importance;
+mod search;
+mod smoothing;
+mod snapshot;
+mod statistics;
+#[cfg(test)]
+mod test_server;
+mod units;
 
-use chrono::{Date, Datelike, Utc};
+pub use bls_api::{BlsApiClient, BlsApiError};
+pub use btc_price_history::PriceBasis;
+use chrono::{Date, Datelike, TimeZone, Utc};
 use cpi_ap::{Area, Item};
 pub use cpi_ap::{AreaCode, ItemCode};
-use dated_series::DatedSeries;
+use cpi_query_engine::{CpiDataset, CpiQueryEngine};
+pub use dated_series::{get_next_month_start, InterpolationInterval, InterpolationStrategy};
+use dated_series::{DateRange, DatedSeries};
+use expression::{Expression, SeriesReference};
+use item_categories::ItemCategory;
+use lru_cache::LruCache;
+use regional_comparison::BPIRegionalComparison;
+use search::SearchResult;
 use serde::Serialize;
+pub use smoothing::{smooth_series, SmoothingMethod};
+pub use statistics::get_series_statistics;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+pub use units::Unit;
+
+/// Cheaply-cloneable handle to the current `BPIEngine`. Engines are never modified
+/// in place. Updates build a new engine in the background and then swap it in, so
+/// in-flight requests keep using the engine they started with.
+#[derive(Clone)]
+pub struct SharedBPIEngine {
+    current_engine: Arc<RwLock<Arc<BPIEngine>>>,
+    /// Held while each update builds and swaps in its engine, so that concurrent
+    /// updates can't overwrite each other's changes. Anything fetched over the
+    /// network is fetched before taking it.
+    update_lock: Arc<rocket::tokio::sync::Mutex<()>>,
+}
+
+impl SharedBPIEngine {
+    pub fn new(bpi_engine: BPIEngine) -> Self {
+        Self {
+            current_engine: Arc::from(RwLock::new(Arc::from(bpi_engine))),
+            update_lock: Arc::from(rocket::tokio::sync::Mutex::new(())),
+        }
+    }
+
+    /// Returns the most recently built engine.
+    pub fn get(&self) -> Arc<BPIEngine> {
+        self.current_engine.read().unwrap().clone()
+    }
+
+    /// Reloads BTC prices from all providers and swaps in an engine built from them.
+    pub async fn refresh_btc_prices(&self) {
+        // Fetched before taking the update lock, so that a slow provider doesn't
+        // hold up other updates.
+        let latest_btc_prices = self.get().btc_price_history.load_latest_prices().await;
+
+        let _update_guard = self.update_lock.lock().await;
+
+        let current_engine = self.get();
+        let btc_price_history = Arc::from(
+            current_engine
+                .btc_price_history
+                .with_latest_prices(latest_btc_prices),
+        );
+        let cpi_query_engine = current_engine.cpi_query_engine.clone();
+        let cpi_index_query_engine = current_engine.cpi_index_query_engine.clone();
+
+        // Recomputing the valid series ranges is CPU-heavy, so it's kept off of the
+        // async worker threads that are serving requests.
+        let new_engine = rocket::tokio::task::spawn_blocking(move || {
+            BPIEngine::from_parts(cpi_query_engine, cpi_index_query_engine, btc_price_history)
+        })
+        .await
+        .unwrap();
+
+        *self.current_engine.write().unwrap() = Arc::from(new_engine);
+    }
+
+    /// Reloads the CPI flat files from disk and swaps in an engine built from them.
+    /// If any of the files are missing or malformed, the current engine is kept.
+    pub async fn reload_cpi_data(&self) -> Result<(), String> {
+        let _update_guard = self.update_lock.lock().await;
+
+        let current_engine = self.get();
+        let btc_price_history = current_engine.btc_price_history.clone();
+
+        let new_engine = rocket::tokio::task::spawn_blocking(move || {
+            Ok::<_, String>(BPIEngine::from_parts(
+                Arc::from(current_engine.cpi_query_engine.reload()?),
+                Arc::from(current_engine.cpi_index_query_engine.reload()?),
+                btc_price_history,
+            ))
+        })
+        .await
+        .unwrap()?;
+
+        *self.current_engine.write().unwrap() = Arc::from(new_engine);
+
+        Ok(())
+    }
+
+    /// Fetches the given CPI average price series from the BLS API and swaps in an
+    /// engine with them merged into the existing CPI data. Returns the number of
+    /// monthly values that were fetched.
+    pub async fn fetch_bls_series(
+        &self,
+        bls_api_client: &BlsApiClient,
+        series_ids: &[String],
+        start_year: i32,
+        end_year: i32,
+    ) -> Result<usize, BlsApiError> {
+        // Fetched before taking the update lock, so that a slow response doesn't
+        // hold up other updates.
+        let series_entries = bls_api_client
+            .fetch_series(series_ids, start_year, end_year)
+            .await?;
+        let series_entry_count = series_entries.len();
+
+        let _update_guard = self.update_lock.lock().await;
+
+        let current_engine = self.get();
+        let btc_price_history = current_engine.btc_price_history.clone();
+
+        let new_engine = rocket::tokio::task::spawn_blocking(move || {
+            BPIEngine::from_parts(
+                Arc::from(
+                    current_engine
+                        .cpi_query_engine
+                        .with_series_entries(series_entries),
+                ),
+                current_engine.cpi_index_query_engine.clone(),
+                btc_price_history,
+            )
+        })
+        .await
+        .unwrap();
+
+        *self.current_engine.write().unwrap() = Arc::from(new_engine);
+
+        Ok(series_entry_count)
+    }
+
+    /// Refreshes BTC prices forever, waiting `period` between each refresh.
+    pub async fn refresh_btc_prices_periodically(self, period: std::time::Duration) {
+        loop {
+            rocket::tokio::time::sleep(period).await;
+            println!("Refreshing BTC price history...");
+            self.refresh_btc_prices().await;
+            println!("BTC price history refresh complete!");
+        }
+    }
+}
+
+/// Maximum number of series kept in each engine's series cache.
+const SERIES_CACHE_CAPACITY: usize = 256;
+
+/// Every parameter that affects the result of `BPIEngine::get_series_data`.
+#[derive(PartialEq, Eq, Hash, Clone)]
+struct SeriesCacheKey {
+    item_code: ItemCode,
+    area_code: AreaCode,
+    start_or: Option<Date<Utc>>,
+    end_or: Option<Date<Utc>>,
+    interpolation_interval: InterpolationInterval,
+    price_basis: PriceBasis,
+    interpolation_strategy: InterpolationStrategy,
+}
 
 pub struct BPIEngine {
-    cpi_query_engine: cpi_query_engine::CpiQueryEngine,
-    btc_price_history: btc_price_history::BTCPriceHistory,
+    cpi_query_engine: Arc<CpiQueryEngine>,
+    cpi_index_query_engine: Arc<CpiQueryEngine>,
+    btc_price_history: Arc<btc_price_history::BTCPriceHistory>,
     computed_valid_series_ranges: Vec<BPISeriesRange>,
+    item_categories: Vec<ItemCategory>,
+    /// Recently computed results of `get_series_data`. Each engine has its own cache,
+    /// so cached series are dropped whenever the underlying data changes.
+    series_cache: Mutex<LruCache<SeriesCacheKey, Arc<Vec<BPISeriesEntry>>>>,
 }
 
 impl BPIEngine {
-    pub fn new() -> Self {
+    /// Builds an engine from the CPI flat files in `cpi_data_dir_or`,
+    /// or from the embedded copies if no directory is given.
+    pub async fn new(cpi_data_dir_or: Option<PathBuf>) -> Self {
+        Self::from_parts(
+            Arc::from(CpiQueryEngine::new_or_embedded(
+                CpiDataset::AveragePrice,
+                cpi_data_dir_or.clone(),
+            )),
+            Arc::from(CpiQueryEngine::new_or_embedded(
+                CpiDataset::Index,
+                cpi_data_dir_or,
+            )),
+            Arc::from(
+                btc_price_history::BTCPriceHistory::new(
+                    btc_price_provider::get_default_providers(),
+                )
+                .await
+                .unwrap(),
+            ),
+        )
+    }
+
+    /// Loads an engine from a snapshot previously written by `write_snapshot`.
+    /// Returns `None`, after logging why, if the snapshot is missing or wasn't
+    /// built from the current CPI data files, BTC price providers and server binary.
+    pub fn load_snapshot_or(
+        cpi_data_dir_or: Option<PathBuf>,
+        snapshot_path: &Path,
+    ) -> Option<Self> {
+        let btc_price_providers = btc_price_provider::get_default_providers();
+        let btc_provider_names: Vec<String> = btc_price_providers
+            .iter()
+            .map(|provider| provider.get_name())
+            .collect();
+
+        let snapshot = match snapshot::read_snapshot(
+            snapshot_path,
+            cpi_data_dir_or.as_deref(),
+            &btc_provider_names,
+        ) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                println!(
+                    "Not using BPI index snapshot {}: {}",
+                    snapshot_path.display(),
+                    e
+                );
+                return None;
+            }
+        };
+
+        let btc_price_history = btc_price_history::BTCPriceHistory::from_snapshot(
+            btc_price_providers,
+            snapshot.btc_price_columns,
+        )?;
+
+        Some(Self::from_parts(
+            Arc::from(snapshot.cpi_query_engine),
+            Arc::from(snapshot.cpi_index_query_engine),
+            Arc::from(btc_price_history),
+        ))
+    }
+
+    /// Writes the engine's parsed CPI and BTC price data to `snapshot_path`, so that
+    /// later startups can skip parsing with `load_snapshot_or`.
+    pub fn write_snapshot(
+        &self,
+        cpi_data_dir_or: Option<&Path>,
+        snapshot_path: &Path,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        snapshot::write_snapshot(
+            snapshot_path,
+            cpi_data_dir_or,
+            &self.btc_price_history.get_provider_names(),
+            &self.cpi_query_engine,
+            &self.cpi_index_query_engine,
+            self.btc_price_history.get_snapshot_price_columns(),
+        )
+    }
+
+    /// Builds an engine from in-memory data, with the same BTC prices for every
+    /// price basis. Series entries are `(area_code, item_code, year, month, value)`.
+    #[cfg(test)]
+    pub fn from_test_data(
+        average_prices: &[(&str, &str, i32, u32, f64)],
+        index_values: &[(&str, &str, i32, u32, f64)],
+        btc_prices: &[(Date<Utc>, f64)],
+    ) -> Self {
+        let get_series_entries = |values: &[(&str, &str, i32, u32, f64)]| {
+            values
+                .iter()
+                .map(|(area_code, item_code, year, month, value)| {
+                    cpi_ap::SeriesEntry::new(area_code, item_code, *year, *month, *value)
+                })
+                .collect()
+        };
+        let btc_price_series = DatedSeries::new(btc_prices.iter().copied().collect());
+        let btc_price_history = btc_price_history::BTCPriceHistory::from_snapshot(
+            vec![Box::from(btc_price_provider::EmbeddedCsvProvider)],
+            vec![Some(btc_price_history::BTCPriceColumns::new(
+                Some(btc_price_series.clone()),
+                Some(btc_price_series.clone()),
+                Some(btc_price_series.clone()),
+                Some(btc_price_series),
+                None,
+            ))],
+        )
+        .unwrap();
+
+        Self::from_parts(
+            Arc::from(CpiQueryEngine::from_series_entries(
+                CpiDataset::AveragePrice,
+                get_series_entries(average_prices),
+            )),
+            Arc::from(CpiQueryEngine::from_series_entries(
+                CpiDataset::Index,
+                get_series_entries(index_values),
+            )),
+            Arc::from(btc_price_history),
+        )
+    }
+
+    fn from_parts(
+        cpi_query_engine: Arc<CpiQueryEngine>,
+        cpi_index_query_engine: Arc<CpiQueryEngine>,
+        btc_price_history: Arc<btc_price_history::BTCPriceHistory>,
+    ) -> Self {
+        let item_categories = item_categories::build_category_tree(cpi_query_engine.get_items());
         let mut bpi_engine = Self {
-            cpi_query_engine: cpi_query_engine::CpiQueryEngine::new(),
-            btc_price_history: btc_price_history::BTCPriceHistory::new().unwrap(),
+            cpi_query_engine,
+            cpi_index_query_engine,
+            btc_price_history,
             computed_valid_series_ranges: Vec::new(),
+            item_categories,
+            series_cache: Mutex::new(LruCache::new(SERIES_CACHE_CAPACITY)),
         };
 
         bpi_engine.compute_valid_series_ranges();
@@ -36,6 +344,38 @@ impl BPIEngine {
         self.cpi_query_engine.get_items()
     }
 
+    /// Returns the number to multiply an item's prices by to get its price per
+    /// `target_unit`. Fails if the item's unit is unknown or measures something
+    /// different, e.g. converting a price per gallon to a price per kilogram.
+    pub fn get_unit_conversion_factor(
+        &self,
+        item_code: &ItemCode,
+        target_unit: Unit,
+    ) -> Result<f64, String> {
+        let item = self
+            .get_items()
+            .iter()
+            .find(|item| item.get_item_code() == item_code)
+            .ok_or_else(|| format!("Unknown item code: {}", item_code.as_str()))?;
+        let price_unit = item
+            .get_price_unit()
+            .ok_or_else(|| format!("Item {} isn't priced by a known unit", item_code.as_str()))?;
+
+        price_unit
+            .get_conversion_factor(target_unit)
+            .ok_or_else(|| {
+                format!(
+                    "Item {} is priced by {}, which can't be converted to {}",
+                    item_code.as_str(),
+                    price_unit.get_unit().get_abbreviation(),
+                    target_unit.get_abbreviation()
+                )
+            })
+    }
+
+    /// Prices an item in sats over time. `interpolation_strategy` controls how CPI
+    /// prices are estimated between the monthly data points.
+    #[allow(clippy::too_many_arguments)]
     pub fn get_series_data(
         &self,
         item_code: ItemCode,
@@ -43,127 +383,1113 @@ impl BPIEngine {
         start_or: Option<Date<Utc>>,
         end_or: Option<Date<Utc>>,
         interpolation_interval: InterpolationInterval,
-    ) -> Vec<BPISeriesEntry> {
+        price_basis: PriceBasis,
+        interpolation_strategy: InterpolationStrategy,
+    ) -> Arc<Vec<BPISeriesEntry>> {
+        let cache_key = SeriesCacheKey {
+            item_code: item_code.clone(),
+            area_code: area_code.clone(),
+            start_or,
+            end_or,
+            interpolation_interval,
+            price_basis,
+            interpolation_strategy,
+        };
+        if let Some(series_entries) = self.series_cache.lock().unwrap().get(&cache_key) {
+            return series_entries;
+        }
+
         let cpi_item_price_series =
             match self.cpi_query_engine.get_series_data(item_code, area_code) {
                 Some(cpi_series) => cpi_series,
-                None => return Vec::new(),
+                None => return Arc::default(),
             };
 
-        let bitcoin_price_series = self.btc_price_history.get_best_dataset();
+        let bitcoin_price_series = match self.btc_price_history.get_dataset(price_basis) {
+            Some(bitcoin_price_series) => bitcoin_price_series,
+            None => return Arc::default(),
+        };
 
-        Self::slice_bpi_series(
+        let series_entries: Arc<Vec<BPISeriesEntry>> = Arc::from(Self::slice_bpi_series(
             cpi_item_price_series,
             bitcoin_price_series,
             start_or,
             end_or,
             interpolation_interval,
-        )
+            interpolation_strategy,
+        ));
+
+        // The lock isn't held while computing the series, so concurrent requests for
+        // the same uncached series may each compute it. That's harmless.
+        self.series_cache
+            .lock()
+            .unwrap()
+            .insert(cache_key, series_entries.clone());
+
+        series_entries
     }
 
-    pub fn get_valid_series_ranges(&self) -> &Vec<BPISeriesRange> {
-        &self.computed_valid_series_ranges
+    /// Prices an item in sats at each real CPI observation, without interpolating.
+    /// Each CPI value is divided by the average BTC price over that calendar month,
+    /// so months without any BTC price data are skipped.
+    pub fn get_observed_series_data(
+        &self,
+        item_code: ItemCode,
+        area_code: AreaCode,
+        start_or: Option<Date<Utc>>,
+        end_or: Option<Date<Utc>>,
+        price_basis: PriceBasis,
+    ) -> Vec<BPISeriesEntry> {
+        let cpi_item_price_series =
+            match self.cpi_query_engine.get_series_data(item_code, area_code) {
+                Some(cpi_series) => cpi_series,
+                None => return Vec::new(),
+            };
+
+        let bitcoin_price_series = match self.btc_price_history.get_dataset(price_basis) {
+            Some(bitcoin_price_series) => bitcoin_price_series,
+            None => return Vec::new(),
+        };
+
+        cpi_item_price_series
+            .iter_between(start_or, end_or)
+            .filter_map(|(date, value_usd)| {
+                let month_start = Utc.ymd(date.year(), date.month(), 1);
+                let month_end = get_next_month_start(month_start) - chrono::Duration::days(1);
+                Some(BPISeriesEntry {
+                    year: date.year(),
+                    month: date.month(),
+                    day: date.day(),
+                    value_msats: usd_to_exact_msats(
+                        value_usd,
+                        bitcoin_price_series.get_average_price(month_start, month_end)?,
+                    ),
+                    value_usd: Some(value_usd),
+                    observed: true,
+                })
+            })
+            .collect()
     }
 
-    fn compute_valid_series_ranges(&mut self) {
-        let mut series_ranges = Vec::new();
+    /// Prices an item in sats on a single date, along with the BTC price used and the
+    /// known CPI and BTC prices that each side was interpolated from. Returns `None` if
+    /// either series doesn't cover the date.
+    pub fn get_point_in_time_data(
+        &self,
+        item_code: ItemCode,
+        area_code: AreaCode,
+        date: Date<Utc>,
+        price_basis: PriceBasis,
+        interpolation_strategy: InterpolationStrategy,
+    ) -> Option<BPIPointInTimeEntry> {
+        let cpi_item_price_series = self
+            .cpi_query_engine
+            .get_series_data(item_code, area_code)?;
+        let bitcoin_price_series = self.btc_price_history.get_dataset(price_basis)?;
 
-        for item in self.get_items() {
-            for area in self.get_areas() {
-                let series_entries = self.get_series_data(
-                    item.get_item_code().clone(),
-                    area.get_area_code().clone(),
-                    None,
-                    None,
-                    InterpolationInterval::Daily,
-                );
-                if let Some(first_entry) = series_entries.first() {
-                    if let Some(last_entry) = series_entries.last() {
-                        series_ranges.push(BPISeriesRange {
-                            item_code: item.get_item_code().clone(),
-                            area_code: area.get_area_code().clone(),
-                            start_year: first_entry.year,
-                            start_month: first_entry.month,
-                            end_year: last_entry.year,
-                            end_month: last_entry.month,
-                        });
-                    }
+        let value_usd = cpi_item_price_series
+            .get_interpolated_price_with_strategy(date, interpolation_strategy)?;
+        let bitcoin_price_usd = bitcoin_price_series.get_interpolated_price(date)?;
+
+        Some(BPIPointInTimeEntry {
+            year: date.year(),
+            month: date.month(),
+            day: date.day(),
+            value_msats: usd_to_msats(value_usd, bitcoin_price_usd),
+            value_usd,
+            btc_price_usd: bitcoin_price_usd,
+            observed: cpi_item_price_series.get_price(date).is_some(),
+            cpi_points: BPIKnownPrice::from_points(
+                cpi_item_price_series.get_interpolation_points(date),
+            ),
+            btc_points: BPIKnownPrice::from_points(
+                bitcoin_price_series.get_interpolation_points(date),
+            ),
+        })
+    }
+
+    /// Prices a basket of items in sats over time, where each item is multiplied
+    /// by its quantity. Only dates covered by every item in the basket are
+    /// included, and the basket is empty if any item has no data in `area_code`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_basket_series_data(
+        &self,
+        basket_items: &[BasketItem],
+        area_code: AreaCode,
+        start_or: Option<Date<Utc>>,
+        end_or: Option<Date<Utc>>,
+        interpolation_interval: InterpolationInterval,
+        price_basis: PriceBasis,
+        interpolation_strategy: InterpolationStrategy,
+    ) -> Vec<BPISeriesEntry> {
+        if basket_items.is_empty() {
+            return Vec::new();
+        }
+
+        let bitcoin_price_series = match self.btc_price_history.get_dataset(price_basis) {
+            Some(bitcoin_price_series) => bitcoin_price_series,
+            None => return Vec::new(),
+        };
+
+        let mut cpi_item_price_series_with_quantities = Vec::new();
+        let mut start_or = start_or;
+        let mut end_or = end_or;
+        for basket_item in basket_items {
+            let cpi_item_price_series = match self
+                .cpi_query_engine
+                .get_series_data(basket_item.item_code.clone(), area_code.clone())
+            {
+                Some(cpi_series) => cpi_series,
+                None => return Vec::new(),
+            };
+
+            // Narrow the date range down to what every item in the basket covers.
+            let (item_start, item_end) = match (
+                cpi_item_price_series.get_first_shared_date(bitcoin_price_series),
+                cpi_item_price_series.get_last_shared_date(bitcoin_price_series),
+            ) {
+                (Some(item_start), Some(item_end)) => (item_start, item_end),
+                _ => return Vec::new(),
+            };
+            start_or = Some(match start_or {
+                Some(start) => std::cmp::max(start, item_start),
+                None => item_start,
+            });
+            end_or = Some(match end_or {
+                Some(end) => std::cmp::min(end, item_end),
+                None => item_end,
+            });
+
+            cpi_item_price_series_with_quantities
+                .push((cpi_item_price_series, basket_item.quantity));
+        }
+
+        let (start, end) = match (start_or, end_or) {
+            (Some(start), Some(end)) if start <= end => (start, end),
+            _ => return Vec::new(),
+        };
+
+        let mut basket_price_series_or: Option<DatedSeries> = None;
+        for (cpi_item_price_series, quantity) in &cpi_item_price_series_with_quantities {
+            let item_price_series = &cpi_item_price_series.resample(
+                start,
+                end,
+                interpolation_interval,
+                interpolation_strategy,
+            ) * *quantity;
+            basket_price_series_or = Some(match basket_price_series_or {
+                Some(basket_price_series) => &basket_price_series + &item_price_series,
+                None => item_price_series,
+            });
+        }
+        let basket_price_series = match basket_price_series_or {
+            Some(basket_price_series) => basket_price_series,
+            None => return Vec::new(),
+        };
+
+        let msats_series = &(&basket_price_series
+            / &bitcoin_price_series.resample(
+                start,
+                end,
+                interpolation_interval,
+                InterpolationStrategy::Linear,
+            ))
+            * MSATS_PER_BTC;
+
+        basket_price_series
+            .iter()
+            .filter_map(|(date, value_usd)| {
+                Some(BPISeriesEntry {
+                    year: date.year(),
+                    month: date.month(),
+                    day: date.day(),
+                    value_msats: msats_series.get_price(date)?,
+                    value_usd: Some(value_usd),
+                    observed: cpi_item_price_series_with_quantities.iter().all(
+                        |(cpi_item_price_series, _)| {
+                            cpi_item_price_series.get_price(date).is_some()
+                        },
+                    ),
+                })
+            })
+            .collect()
+    }
+
+    /// Prices several item/area pairs on a shared date axis. By default the axis
+    /// covers every date that any of the series has data for, and series without
+    /// data on a date have a `null` value there. If `intersection_only` is set, the
+    /// axis only covers dates that every series has data for.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_comparison_series_data(
+        &self,
+        series_keys: Vec<SeriesKey>,
+        start_or: Option<Date<Utc>>,
+        end_or: Option<Date<Utc>>,
+        interpolation_interval: InterpolationInterval,
+        price_basis: PriceBasis,
+        interpolation_strategy: InterpolationStrategy,
+        intersection_only: bool,
+    ) -> BPIComparison {
+        let bitcoin_price_series_or = self.btc_price_history.get_dataset(price_basis);
+        let cpi_item_price_series_list: Vec<Option<&DatedSeries>> = series_keys
+            .iter()
+            .map(|series_key| {
+                self.cpi_query_engine
+                    .get_series_data(series_key.item_code.clone(), series_key.area_code.clone())
+            })
+            .collect();
+
+        let mut first_dates = Vec::new();
+        let mut last_dates = Vec::new();
+        if let Some(bitcoin_price_series) = bitcoin_price_series_or {
+            for cpi_item_price_series in cpi_item_price_series_list.iter().flatten() {
+                if let (Some(first_date), Some(last_date)) = (
+                    cpi_item_price_series.get_first_shared_date(bitcoin_price_series),
+                    cpi_item_price_series.get_last_shared_date(bitcoin_price_series),
+                ) {
+                    first_dates.push(first_date);
+                    last_dates.push(last_date);
                 }
             }
         }
 
-        self.computed_valid_series_ranges = series_ranges;
+        // Series without any data make the intersection empty.
+        let has_missing_series = first_dates.len() < series_keys.len();
+        let range_or = if intersection_only {
+            match (first_dates.iter().max(), last_dates.iter().min()) {
+                (Some(start), Some(end)) if !has_missing_series => Some((*start, *end)),
+                _ => None,
+            }
+        } else {
+            match (first_dates.iter().min(), last_dates.iter().max()) {
+                (Some(start), Some(end)) => Some((*start, *end)),
+                _ => None,
+            }
+        };
+
+        let mut dates = Vec::new();
+        if let (Some((mut start, mut end)), Some(bitcoin_price_series)) =
+            (range_or, bitcoin_price_series_or)
+        {
+            if let Some(start_override) = start_or {
+                start = std::cmp::max(start, start_override);
+            }
+            if let Some(end_override) = end_or {
+                end = std::cmp::min(end, end_override);
+            }
+            if start <= end {
+                dates = DateRange::new(start, end, interpolation_interval)
+                    .filter(|date| bitcoin_price_series.get_interpolated_price(*date).is_some())
+                    .collect();
+            }
+        }
+
+        let entries = dates
+            .into_iter()
+            .map(|date| BPIComparisonEntry {
+                year: date.year(),
+                month: date.month(),
+                day: date.day(),
+                values: cpi_item_price_series_list
+                    .iter()
+                    .map(|cpi_item_price_series_or| {
+                        Self::get_series_entry_or(
+                            (*cpi_item_price_series_or)?,
+                            bitcoin_price_series_or?,
+                            date,
+                            interpolation_strategy,
+                        )
+                        .map(|series_entry| BPIComparisonValue {
+                            value_msats: series_entry.get_value_msats(),
+                            value_usd: series_entry.value_usd,
+                            observed: series_entry.observed,
+                        })
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        BPIComparison {
+            series: series_keys,
+            entries,
+        }
     }
 
-    fn slice_bpi_series(
-        cpi_item_price_series: &DatedSeries,
-        bitcoin_price_series: &DatedSeries,
+    /// Compares an item's sats price across every area that has data for it. Each
+    /// area's price is the average of its monthly values between `start_or` and
+    /// `end_or`, so passing the same date for both compares a single month. If
+    /// neither is given, areas are compared on the latest date that any of them
+    /// has data for.
+    pub fn get_regional_comparison_data(
+        &self,
+        item_code: ItemCode,
+        start_or: Option<Date<Utc>>,
+        end_or: Option<Date<Utc>>,
+        price_basis: PriceBasis,
+        interpolation_strategy: InterpolationStrategy,
+    ) -> BPIRegionalComparison {
+        let bitcoin_price_series = match self.btc_price_history.get_dataset(price_basis) {
+            Some(bitcoin_price_series) => bitcoin_price_series,
+            None => return regional_comparison::build_regional_comparison(item_code, Vec::new()),
+        };
+
+        let cpi_item_price_series_by_area: Vec<(&Area, &DatedSeries)> = self
+            .get_areas()
+            .iter()
+            .filter_map(|area| {
+                Some((
+                    area,
+                    self.cpi_query_engine
+                        .get_series_data(item_code.clone(), area.get_area_code().clone())?,
+                ))
+            })
+            .collect();
+
+        let (start_or, end_or) = match (start_or, end_or) {
+            (None, None) => {
+                let latest_date_or = cpi_item_price_series_by_area
+                    .iter()
+                    .filter_map(|(_, cpi_item_price_series)| {
+                        cpi_item_price_series.get_last_shared_date(bitcoin_price_series)
+                    })
+                    .max();
+                (latest_date_or, latest_date_or)
+            }
+            date_range => date_range,
+        };
+
+        let area_values = cpi_item_price_series_by_area
+            .into_iter()
+            .filter_map(|(area, cpi_item_price_series)| {
+                let series_entries = Self::slice_bpi_series(
+                    cpi_item_price_series,
+                    bitcoin_price_series,
+                    start_or,
+                    end_or,
+                    InterpolationInterval::Monthly,
+                    interpolation_strategy,
+                );
+                if series_entries.is_empty() {
+                    return None;
+                }
+
+                let entry_count = series_entries.len() as f64;
+                let value_msats = series_entries
+                    .iter()
+                    .map(|entry| entry.get_value_msats() as f64)
+                    .sum::<f64>()
+                    / entry_count;
+                let value_usd = series_entries
+                    .iter()
+                    .filter_map(|entry| entry.value_usd)
+                    .sum::<f64>()
+                    / entry_count;
+                Some((area.clone(), value_msats.round() as u64, value_usd))
+            })
+            .collect();
+
+        regional_comparison::build_regional_comparison(item_code, area_values)
+    }
+
+    pub fn get_index_areas(&self) -> &Vec<Area> {
+        self.cpi_index_query_engine.get_areas()
+    }
+
+    pub fn get_index_items(&self) -> &Vec<Item> {
+        self.cpi_index_query_engine.get_items()
+    }
+
+    /// Whether any CPI-U index series are loaded. The `cu` flat files aren't
+    /// embedded, so there are none unless they're in the CPI data directory.
+    pub fn has_index_data(&self) -> bool {
+        self.cpi_index_query_engine.has_series_data()
+    }
+
+    /// Returns a CPI-U index series divided by the BTC price, rebased so that its
+    /// value on `base_date_or` is 100. Defaults to rebasing on the first date
+    /// that both series share. Returns `None` if there's no CPI-U series for
+    /// `item_code` in `area_code`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_index_series_data(
+        &self,
+        item_code: ItemCode,
+        area_code: AreaCode,
+        base_date_or: Option<Date<Utc>>,
         start_or: Option<Date<Utc>>,
         end_or: Option<Date<Utc>>,
         interpolation_interval: InterpolationInterval,
-    ) -> Vec<BPISeriesEntry> {
-        let mut start = match cpi_item_price_series.get_first_shared_date(bitcoin_price_series) {
+        price_basis: PriceBasis,
+        interpolation_strategy: InterpolationStrategy,
+    ) -> Option<Vec<BPIIndexEntry>> {
+        let cpi_index_series = self
+            .cpi_index_query_engine
+            .get_series_data(item_code, area_code)?;
+
+        let bitcoin_price_series = match self.btc_price_history.get_dataset(price_basis) {
+            Some(bitcoin_price_series) => bitcoin_price_series,
+            None => return Some(Vec::new()),
+        };
+
+        let base_date = match base_date_or
+            .or_else(|| cpi_index_series.get_first_shared_date(bitcoin_price_series))
+        {
+            Some(base_date) => base_date,
+            None => return Some(Vec::new()),
+        };
+        let base_value = match (
+            cpi_index_series
+                .get_interpolated_price_with_strategy(base_date, interpolation_strategy),
+            bitcoin_price_series.get_interpolated_price(base_date),
+        ) {
+            (Some(cpi_value), Some(bitcoin_price)) => cpi_value / bitcoin_price,
+            _ => return Some(Vec::new()),
+        };
+
+        let (aligned_cpi_index_series, aligned_bitcoin_price_series) = cpi_index_series.align(
+            bitcoin_price_series,
+            start_or,
+            end_or,
+            interpolation_interval,
+            interpolation_strategy,
+            InterpolationStrategy::Linear,
+        );
+
+        Some(
+            (&(&aligned_cpi_index_series / &aligned_bitcoin_price_series) * (100.0 / base_value))
+                .iter()
+                .map(|(date, value)| BPIIndexEntry {
+                    year: date.year(),
+                    month: date.month(),
+                    day: date.day(),
+                    value,
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the BTC price deflated into constant dollars of `base_date_or`, using
+    /// the CPI-U index series for `item_code` in `area_code`. Defaults to dollars of
+    /// the latest month with CPI data. Dates after the last CPI observation aren't
+    /// included, since there's no CPI value to deflate them by. Returns `None` if
+    /// there's no CPI-U series for `item_code` in `area_code`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_real_btc_price_series_data(
+        &self,
+        item_code: ItemCode,
+        area_code: AreaCode,
+        base_date_or: Option<Date<Utc>>,
+        start_or: Option<Date<Utc>>,
+        end_or: Option<Date<Utc>>,
+        interpolation_interval: InterpolationInterval,
+        price_basis: PriceBasis,
+        interpolation_strategy: InterpolationStrategy,
+    ) -> Option<Vec<BPIRealPriceEntry>> {
+        let cpi_index_series = self
+            .cpi_index_query_engine
+            .get_series_data(item_code, area_code)?;
+
+        let bitcoin_price_series = match self.btc_price_history.get_dataset(price_basis) {
+            Some(bitcoin_price_series) => bitcoin_price_series,
+            None => return Some(Vec::new()),
+        };
+
+        let base_date =
+            match base_date_or.or_else(|| cpi_index_series.get_last_entry_date().copied()) {
+                Some(base_date) => base_date,
+                None => return Some(Vec::new()),
+            };
+        let base_cpi_value = match cpi_index_series
+            .get_interpolated_price_with_strategy(base_date, interpolation_strategy)
+        {
+            Some(base_cpi_value) if base_cpi_value > 0.0 => base_cpi_value,
+            _ => return Some(Vec::new()),
+        };
+
+        let (aligned_cpi_index_series, aligned_bitcoin_price_series) = cpi_index_series.align(
+            bitcoin_price_series,
+            start_or,
+            end_or,
+            interpolation_interval,
+            interpolation_strategy,
+            InterpolationStrategy::Linear,
+        );
+        let real_bitcoin_price_series =
+            &(&aligned_bitcoin_price_series / &aligned_cpi_index_series) * base_cpi_value;
+
+        Some(
+            real_bitcoin_price_series
+                .iter()
+                .filter_map(|(date, real_usd)| {
+                    Some(BPIRealPriceEntry {
+                        year: date.year(),
+                        month: date.month(),
+                        day: date.day(),
+                        nominal_usd: aligned_bitcoin_price_series.get_price(date)?,
+                        real_usd,
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns a composite "Bitcoin CPI" built from the average price series in
+    /// `area_code`, with each item weighted by its BLS relative importance. The index
+    /// is priced in sats and rebased so that its value on `base_date_or` is 100.
+    /// Defaults to rebasing on the first date that every weighted item has data for.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_weighted_index_series_data(
+        &self,
+        area_code: AreaCode,
+        base_date_or: Option<Date<Utc>>,
+        start_or: Option<Date<Utc>>,
+        end_or: Option<Date<Utc>>,
+        interpolation_interval: InterpolationInterval,
+        price_basis: PriceBasis,
+        interpolation_strategy: InterpolationStrategy,
+    ) -> Vec<BPIIndexEntry> {
+        self.get_composite_index_series_data(
+            self.cpi_query_engine
+                .get_item_weights()
+                .iter()
+                .map(|item_weight| {
+                    (
+                        item_weight.get_item_code().clone(),
+                        item_weight.get_weight(),
+                    )
+                })
+                .collect(),
+            area_code,
+            base_date_or,
+            start_or,
+            end_or,
+            interpolation_interval,
+            price_basis,
+            interpolation_strategy,
+        )
+    }
+
+    pub fn get_item_categories(&self) -> &Vec<ItemCategory> {
+        &self.item_categories
+    }
+
+    /// Same as `get_weighted_index_series_data`, but only includes the items in a
+    /// single category (and its subcategories), weighted equally. Returns `None`
+    /// if the category doesn't exist.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_category_index_series_data(
+        &self,
+        category_code: &str,
+        area_code: AreaCode,
+        base_date_or: Option<Date<Utc>>,
+        start_or: Option<Date<Utc>>,
+        end_or: Option<Date<Utc>>,
+        interpolation_interval: InterpolationInterval,
+        price_basis: PriceBasis,
+        interpolation_strategy: InterpolationStrategy,
+    ) -> Option<Vec<BPIIndexEntry>> {
+        let category = item_categories::find_category(&self.item_categories, category_code)?;
+
+        Some(
+            self.get_composite_index_series_data(
+                category
+                    .get_all_item_codes()
+                    .into_iter()
+                    .map(|item_code| (item_code, 1.0))
+                    .collect(),
+                area_code,
+                base_date_or,
+                start_or,
+                end_or,
+                interpolation_interval,
+                price_basis,
+                interpolation_strategy,
+            ),
+        )
+    }
+
+    /// Builds a sats-denominated index from `(item_code, weight)` pairs, rebased so
+    /// that its value on `base_date_or` is 100. Defaults to rebasing on the first date
+    /// that every weighted item has data for.
+    ///
+    /// Items without a price on the base date are left out entirely. On any other
+    /// date, items without a price are skipped and the remaining weights are scaled
+    /// up to make up for them. The index isn't chain-linked, so it can jump on dates
+    /// where an item starts or stops having data.
+    #[allow(clippy::too_many_arguments)]
+    fn get_composite_index_series_data(
+        &self,
+        item_weights: Vec<(ItemCode, f64)>,
+        area_code: AreaCode,
+        base_date_or: Option<Date<Utc>>,
+        start_or: Option<Date<Utc>>,
+        end_or: Option<Date<Utc>>,
+        interpolation_interval: InterpolationInterval,
+        price_basis: PriceBasis,
+        interpolation_strategy: InterpolationStrategy,
+    ) -> Vec<BPIIndexEntry> {
+        let bitcoin_price_series = match self.btc_price_history.get_dataset(price_basis) {
+            Some(bitcoin_price_series) => bitcoin_price_series,
+            None => return Vec::new(),
+        };
+
+        let mut weighted_series = Vec::new();
+        let mut first_dates = Vec::new();
+        let mut last_dates = Vec::new();
+        for (item_code, weight) in item_weights {
+            let cpi_item_price_series = match self
+                .cpi_query_engine
+                .get_series_data(item_code, area_code.clone())
+            {
+                Some(cpi_series) => cpi_series,
+                None => continue,
+            };
+
+            if let (Some(first_date), Some(last_date)) = (
+                cpi_item_price_series.get_first_shared_date(bitcoin_price_series),
+                cpi_item_price_series.get_last_shared_date(bitcoin_price_series),
+            ) {
+                first_dates.push(first_date);
+                last_dates.push(last_date);
+                weighted_series.push((cpi_item_price_series, weight));
+            }
+        }
+
+        let base_date = match base_date_or.or_else(|| first_dates.iter().max().copied()) {
+            Some(base_date) => base_date,
+            None => return Vec::new(),
+        };
+        let base_bitcoin_price = match bitcoin_price_series.get_interpolated_price(base_date) {
+            Some(base_bitcoin_price) => base_bitcoin_price,
+            None => return Vec::new(),
+        };
+        let weighted_series_with_base_prices: Vec<(&DatedSeries, f64, f64)> = weighted_series
+            .into_iter()
+            .filter_map(|(cpi_item_price_series, weight)| {
+                let base_price = cpi_item_price_series
+                    .get_interpolated_price_with_strategy(base_date, interpolation_strategy)?;
+                if base_price <= 0.0 {
+                    return None;
+                }
+                Some((cpi_item_price_series, weight, base_price))
+            })
+            .collect();
+        if weighted_series_with_base_prices.is_empty() {
+            return Vec::new();
+        }
+
+        let mut start = match first_dates.into_iter().min() {
             Some(start) => start,
             None => return Vec::new(),
         };
         if let Some(start_override) = start_or {
             start = std::cmp::max(start, start_override);
         }
-
-        let mut end = match cpi_item_price_series.get_last_shared_date(bitcoin_price_series) {
-            Some(start) => start,
+        let mut end = match last_dates.into_iter().max() {
+            Some(end) => end,
             None => return Vec::new(),
         };
         if let Some(end_override) = end_or {
-            end = std::cmp::max(end, end_override);
+            end = std::cmp::min(end, end_override);
         }
-
         if start > end {
             return Vec::new();
         }
 
-        Self::interpolate_dates(&start, &end, interpolation_interval)
-            .into_iter()
+        DateRange::new(start, end, interpolation_interval)
             .filter_map(|date| {
-                Some(BPISeriesEntry {
+                let mut total_weight = 0.0;
+                let mut weighted_price_relatives = 0.0;
+                for (cpi_item_price_series, weight, base_price) in &weighted_series_with_base_prices
+                {
+                    if let Some(price) = cpi_item_price_series
+                        .get_interpolated_price_with_strategy(date, interpolation_strategy)
+                    {
+                        total_weight += weight;
+                        weighted_price_relatives += weight * price / base_price;
+                    }
+                }
+                if total_weight <= 0.0 {
+                    return None;
+                }
+
+                Some(BPIIndexEntry {
                     year: date.year(),
                     month: date.month(),
                     day: date.day(),
-                    value_sats: (cpi_item_price_series.get_interpolated_price(date)?
-                        * (1.0 / bitcoin_price_series.get_interpolated_price(date)? * 100000000.0))
-                        as i32,
+                    value: weighted_price_relatives / total_weight * base_bitcoin_price
+                        / bitcoin_price_series.get_interpolated_price(date)?
+                        * 100.0,
                 })
             })
             .collect()
     }
 
-    fn interpolate_dates(
-        start_date: &Date<Utc>,
-        end_date: &Date<Utc>,
-        interval: InterpolationInterval,
-    ) -> Vec<Date<Utc>> {
-        match interval {
-            InterpolationInterval::Daily => {
-                let mut dates = Vec::new();
+    /// Parses and evaluates a series expression, e.g. `ap(701111, 0000) / btc * 1e8`.
+    /// See `Expression` for the syntax. Every series in the expression is sampled on
+    /// the dates at `interpolation_interval` that all of them cover. Returns an error
+    /// if the expression is invalid or references a series that has no data.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_expression_series_data(
+        &self,
+        expression: &str,
+        start_or: Option<Date<Utc>>,
+        end_or: Option<Date<Utc>>,
+        interpolation_interval: InterpolationInterval,
+        price_basis: PriceBasis,
+        interpolation_strategy: InterpolationStrategy,
+    ) -> Result<Vec<BPIExpressionEntry>, String> {
+        let expression = Expression::parse(expression)?;
+
+        let mut source_series = Vec::new();
+        for series_reference in expression.get_series_references() {
+            let (series_or, strategy) = match series_reference {
+                SeriesReference::AveragePrice(item_code, area_code) => (
+                    self.cpi_query_engine
+                        .get_series_data(item_code.clone(), area_code.clone()),
+                    interpolation_strategy,
+                ),
+                SeriesReference::Index(item_code, area_code) => (
+                    self.cpi_index_query_engine
+                        .get_series_data(item_code.clone(), area_code.clone()),
+                    interpolation_strategy,
+                ),
+                SeriesReference::Btc => (
+                    self.btc_price_history.get_dataset(price_basis),
+                    InterpolationStrategy::Linear,
+                ),
+            };
 
-                let mut date = *start_date;
-                while &date <= end_date {
-                    dates.push(date);
-                    date += chrono::Duration::days(1);
+            let series = match series_or {
+                Some(series) => series,
+                None => {
+                    return Err(match series_reference {
+                        SeriesReference::AveragePrice(item_code, area_code) => format!(
+                            "No average price data for item {} in area {}",
+                            item_code.as_str(),
+                            area_code.as_str()
+                        ),
+                        SeriesReference::Index(item_code, area_code) => format!(
+                            "No CPI-U index data for item {} in area {}",
+                            item_code.as_str(),
+                            area_code.as_str()
+                        ),
+                        SeriesReference::Btc => "No BTC price data".to_string(),
+                    })
                 }
-                dates
+            };
+            source_series.push((series_reference, series, strategy));
+        }
+
+        let mut dates = Vec::new();
+        if let (Some(mut start), Some(mut end)) = (
+            source_series
+                .iter()
+                .filter_map(|(_, series, _)| series.get_first_entry_date())
+                .max()
+                .copied(),
+            source_series
+                .iter()
+                .filter_map(|(_, series, _)| series.get_last_entry_date())
+                .min()
+                .copied(),
+        ) {
+            if let Some(start_override) = start_or {
+                start = std::cmp::max(start, start_override);
             }
+            if let Some(end_override) = end_or {
+                end = std::cmp::min(end, end_override);
+            }
+            dates = DateRange::new(start, end, interpolation_interval).collect();
         }
+
+        let sampled_series: HashMap<&SeriesReference, DatedSeries> = source_series
+            .into_iter()
+            .map(|(series_reference, series, strategy)| {
+                (
+                    series_reference,
+                    series.resample_at_dates(dates.iter().copied(), strategy),
+                )
+            })
+            .collect();
+
+        Ok(expression
+            .evaluate(&sampled_series)?
+            .iter()
+            .map(|(date, value)| BPIExpressionEntry {
+                year: date.year(),
+                month: date.month(),
+                day: date.day(),
+                value,
+            })
+            .collect())
+    }
+
+    pub fn get_valid_series_ranges(&self) -> &Vec<BPISeriesRange> {
+        &self.computed_valid_series_ranges
+    }
+
+    /// Searches item and area names, only returning item/area combos that have data.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchResult> {
+        search::search_series(
+            query,
+            self.get_items(),
+            self.get_areas(),
+            &self.computed_valid_series_ranges,
+            limit,
+        )
+    }
+
+    /// Only looks at where each series starts and ends, rather than computing the
+    /// series themselves.
+    fn compute_valid_series_ranges(&mut self) {
+        let mut series_ranges = Vec::new();
+
+        let bitcoin_price_series = match self.btc_price_history.get_dataset(PriceBasis::Open) {
+            Some(bitcoin_price_series) => bitcoin_price_series,
+            None => return,
+        };
+
+        for item in self.get_items() {
+            for area in self.get_areas() {
+                let cpi_item_price_series = match self
+                    .cpi_query_engine
+                    .get_series_data(item.get_item_code().clone(), area.get_area_code().clone())
+                {
+                    Some(cpi_series) => cpi_series,
+                    None => continue,
+                };
+
+                if let (Some(first_date), Some(last_date)) = (
+                    cpi_item_price_series.get_first_shared_date(bitcoin_price_series),
+                    cpi_item_price_series.get_last_shared_date(bitcoin_price_series),
+                ) {
+                    series_ranges.push(BPISeriesRange {
+                        item_code: item.get_item_code().clone(),
+                        area_code: area.get_area_code().clone(),
+                        start_year: first_date.year(),
+                        start_month: first_date.month(),
+                        end_year: last_date.year(),
+                        end_month: last_date.month(),
+                    });
+                }
+            }
+        }
+
+        self.computed_valid_series_ranges = series_ranges;
+    }
+
+    fn slice_bpi_series(
+        cpi_item_price_series: &DatedSeries,
+        bitcoin_price_series: &DatedSeries,
+        start_or: Option<Date<Utc>>,
+        end_or: Option<Date<Utc>>,
+        interpolation_interval: InterpolationInterval,
+        interpolation_strategy: InterpolationStrategy,
+    ) -> Vec<BPISeriesEntry> {
+        let (aligned_cpi_item_price_series, aligned_bitcoin_price_series) = cpi_item_price_series
+            .align(
+                bitcoin_price_series,
+                start_or,
+                end_or,
+                interpolation_interval,
+                interpolation_strategy,
+                InterpolationStrategy::Linear,
+            );
+        let msats_series =
+            &(&aligned_cpi_item_price_series / &aligned_bitcoin_price_series) * MSATS_PER_BTC;
+
+        aligned_cpi_item_price_series
+            .iter()
+            .filter_map(|(date, value_usd)| {
+                Some(BPISeriesEntry {
+                    year: date.year(),
+                    month: date.month(),
+                    day: date.day(),
+                    value_msats: msats_series.get_price(date)?,
+                    value_usd: Some(value_usd),
+                    observed: cpi_item_price_series.get_price(date).is_some(),
+                })
+            })
+            .collect()
+    }
+
+    /// Prices an item in sats on a single date. Returns `None` if either
+    /// series doesn't cover the date.
+    fn get_series_entry_or(
+        cpi_item_price_series: &DatedSeries,
+        bitcoin_price_series: &DatedSeries,
+        date: Date<Utc>,
+        interpolation_strategy: InterpolationStrategy,
+    ) -> Option<BPISeriesEntry> {
+        let value_usd = cpi_item_price_series
+            .get_interpolated_price_with_strategy(date, interpolation_strategy)?;
+        Some(BPISeriesEntry {
+            year: date.year(),
+            month: date.month(),
+            day: date.day(),
+            value_msats: usd_to_exact_msats(
+                value_usd,
+                bitcoin_price_series.get_interpolated_price(date)?,
+            ),
+            value_usd: Some(value_usd),
+            observed: cpi_item_price_series.get_price(date).is_some(),
+        })
     }
 }
 
-#[derive(Serialize)]
+/// Number of millisats in one bitcoin.
+const MSATS_PER_BTC: f64 = 100_000_000_000.0;
+
+/// Converts a USD amount to millisats at the given BTC price, rounding to the
+/// nearest millisat.
+fn usd_to_msats(value_usd: f64, bitcoin_price_usd: f64) -> u64 {
+    usd_to_exact_msats(value_usd, bitcoin_price_usd).round() as u64
+}
+
+/// Same as `usd_to_msats`, but without rounding.
+fn usd_to_exact_msats(value_usd: f64, bitcoin_price_usd: f64) -> f64 {
+    value_usd / bitcoin_price_usd * MSATS_PER_BTC
+}
+
+#[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct BPISeriesEntry {
     year: i32,
     month: u32,
     day: u32,
-    value_sats: i32,
+    /// Value in millisats (thousandths of a sat), so that cheap items
+    /// don't lose precision and expensive ones don't overflow. Kept unrounded
+    /// until it's serialized, so that scaling doesn't compound rounding errors.
+    #[serde(serialize_with = "serialize_rounded_msats")]
+    value_msats: f64,
+    /// Value in US dollars, if the series is priced in dollars.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_usd: Option<f64>,
+    /// Whether this entry falls on a real CPI observation. Otherwise
+    /// its CPI value was interpolated from the surrounding observations.
+    observed: bool,
+}
+
+impl BPISeriesEntry {
+    /// Multiplies both values by `factor`, e.g. to price a different quantity.
+    pub fn scale(self, factor: f64) -> Self {
+        Self {
+            value_msats: self.value_msats * factor,
+            value_usd: self.value_usd.map(|value_usd| value_usd * factor),
+            ..self
+        }
+    }
+
+    /// Returns the value in millisats, rounded to the nearest millisat.
+    pub fn get_value_msats(&self) -> u64 {
+        self.value_msats.round() as u64
+    }
+}
+
+fn serialize_rounded_msats<S: serde::Serializer>(
+    value_msats: &f64,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(value_msats.round() as u64)
+}
+
+/// Several series priced on a shared date axis.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BPIComparison {
+    series: Vec<SeriesKey>,
+    entries: Vec<BPIComparisonEntry>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BPIComparisonEntry {
+    year: i32,
+    month: u32,
+    day: u32,
+    /// One value per series, in the same order as `BPIComparison::series`.
+    /// `None` wherever a series has no data on this date.
+    values: Vec<Option<BPIComparisonValue>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BPIComparisonValue {
+    value_msats: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_usd: Option<f64>,
+    observed: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BPIIndexEntry {
+    year: i32,
+    month: u32,
+    day: u32,
+    value: f64,
+}
+
+/// An item's sats price on one date, with everything that went into computing it.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BPIPointInTimeEntry {
+    year: i32,
+    month: u32,
+    day: u32,
+    value_msats: u64,
+    value_usd: f64,
+    btc_price_usd: f64,
+    /// Whether the date falls on a real CPI observation.
+    observed: bool,
+    /// The CPI observation on the date, or the observations before and after it
+    /// that `value_usd` was interpolated between.
+    cpi_points: Vec<BPIKnownPrice>,
+    /// Same as `cpi_points`, but for `btc_price_usd`.
+    btc_points: Vec<BPIKnownPrice>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BPIKnownPrice {
+    year: i32,
+    month: u32,
+    day: u32,
+    value_usd: f64,
+}
+
+impl BPIKnownPrice {
+    fn from_points(points: Vec<(Date<Utc>, f64)>) -> Vec<Self> {
+        points
+            .into_iter()
+            .map(|(date, value_usd)| Self {
+                year: date.year(),
+                month: date.month(),
+                day: date.day(),
+                value_usd,
+            })
+            .collect()
+    }
+}
+
+/// The BTC price on one date, in both that date's dollars and constant dollars.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BPIRealPriceEntry {
+    year: i32,
+    month: u32,
+    day: u32,
+    nominal_usd: f64,
+    real_usd: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BPIExpressionEntry {
+    year: i32,
+    month: u32,
+    day: u32,
+    value: f64,
 }
 
 #[derive(Serialize)]
@@ -177,9 +1503,474 @@ pub struct BPISeriesRange {
     end_month: u32,
 }
 
-pub enum InterpolationInterval {
-    Daily,
-    // TODO - Uncomment the values below and implement them where necessary.
-    // Weekly,
-    // Monthly
+/// A quantity of a single item in a basket. Parsed from query parameters in the
+/// format `<item_code>:<quantity>`, e.g. `701111:2` for 2 lb of flour. The
+/// quantity is in the item's own unit and defaults to 1 if omitted.
+pub struct BasketItem {
+    item_code: ItemCode,
+    quantity: f64,
+}
+
+impl<'a> rocket::form::FromFormField<'a> for BasketItem {
+    fn from_value(field: rocket::form::ValueField<'a>) -> rocket::form::Result<'a, Self> {
+        let (item_code, quantity) = match field.value.split_once(':') {
+            Some((item_code, quantity)) => match quantity.parse::<f64>() {
+                Ok(quantity) if quantity.is_finite() && quantity >= 0.0 => (item_code, quantity),
+                _ => {
+                    return Err(rocket::form::Error::validation(format!(
+                        "Invalid basket item quantity: {}",
+                        quantity
+                    ))
+                    .into())
+                }
+            },
+            None => (field.value, 1.0),
+        };
+
+        if item_code.is_empty() {
+            return Err(rocket::form::Error::validation(format!(
+                "Missing basket item code: {}",
+                field.value
+            ))
+            .into());
+        }
+
+        Ok(Self {
+            item_code: ItemCode::new(item_code),
+            quantity,
+        })
+    }
+}
+
+/// A single item/area pair. Parsed from query parameters in the
+/// format `<item_code>:<area_code>`, e.g. `708111:0000`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeriesKey {
+    item_code: ItemCode,
+    area_code: AreaCode,
+}
+
+impl<'a> rocket::form::FromFormField<'a> for SeriesKey {
+    fn from_value(field: rocket::form::ValueField<'a>) -> rocket::form::Result<'a, Self> {
+        match field.value.split_once(':') {
+            Some((item_code, area_code)) if !item_code.is_empty() && !area_code.is_empty() => {
+                Ok(Self {
+                    item_code: ItemCode::new(item_code),
+                    area_code: AreaCode::new(area_code),
+                })
+            }
+            _ => Err(rocket::form::Error::validation(format!(
+                "Expected series in the format `<item_code>:<area_code>`: {}",
+                field.value
+            ))
+            .into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Prices BTC at $20,000 every day of 2022 and 2023, so one dollar buys
+    /// 5,000,000 millisats.
+    fn get_btc_prices() -> Vec<(Date<Utc>, f64)> {
+        let mut btc_prices = Vec::new();
+        let mut date = Utc.ymd(2022, 1, 1);
+        while date < Utc.ymd(2024, 1, 1) {
+            btc_prices.push((date, 20000.0));
+            date = date.succ();
+        }
+        btc_prices
+    }
+
+    /// One average price per month in area 0000 from `start_month` to `end_month`
+    /// of 2022, inclusive.
+    fn get_monthly_prices(
+        item_code: &'static str,
+        start_month: u32,
+        end_month: u32,
+        value: impl Fn(u32) -> f64,
+    ) -> Vec<(&'static str, &'static str, i32, u32, f64)> {
+        (start_month..=end_month)
+            .map(|month| ("0000", item_code, 2022, month, value(month)))
+            .collect()
+    }
+
+    fn get_dates_and_values(series_entries: &[BPISeriesEntry]) -> Vec<((u32, u32), u64, bool)> {
+        series_entries
+            .iter()
+            .map(|entry| {
+                (
+                    (entry.month, entry.day),
+                    entry.get_value_msats(),
+                    entry.observed,
+                )
+            })
+            .collect()
+    }
+
+    /// Same as `get_btc_prices`, but BTC doubles to $40,000 from July 2022 onward.
+    fn get_doubling_btc_prices() -> Vec<(Date<Utc>, f64)> {
+        get_btc_prices()
+            .into_iter()
+            .map(|(date, price)| {
+                if date >= Utc.ymd(2022, 7, 1) {
+                    (date, price * 2.0)
+                } else {
+                    (date, price)
+                }
+            })
+            .collect()
+    }
+
+    fn get_months_and_values(index_entries: &[BPIIndexEntry]) -> Vec<(u32, f64)> {
+        index_entries
+            .iter()
+            .map(|entry| (entry.month, entry.value))
+            .collect()
+    }
+
+    #[test]
+    fn weights_basket_items_by_quantity() {
+        let mut average_prices = get_monthly_prices("701111", 1, 6, |_| 0.5);
+        average_prices.extend(get_monthly_prices("708111", 3, 12, |month| month as f64));
+        let bpi_engine = BPIEngine::from_test_data(&average_prices, &[], &get_btc_prices());
+        let basket_items = vec![
+            BasketItem {
+                item_code: ItemCode::new("701111"),
+                quantity: 2.0,
+            },
+            BasketItem {
+                item_code: ItemCode::new("708111"),
+                quantity: 0.5,
+            },
+        ];
+
+        // Only the months that both items cover are included.
+        let series_entries = bpi_engine.get_basket_series_data(
+            &basket_items,
+            AreaCode::new("0000"),
+            None,
+            None,
+            InterpolationInterval::Monthly,
+            PriceBasis::Open,
+            InterpolationStrategy::Linear,
+        );
+        assert_eq!(
+            get_dates_and_values(&series_entries),
+            vec![
+                ((3, 1), 12_500_000, true),
+                ((4, 1), 15_000_000, true),
+                ((5, 1), 17_500_000, true),
+                ((6, 1), 20_000_000, true),
+            ]
+        );
+        assert_eq!(series_entries[0].value_usd, Some(2.5));
+
+        // Entries between observations are interpolated.
+        let series_entries = bpi_engine.get_basket_series_data(
+            &basket_items,
+            AreaCode::new("0000"),
+            Some(Utc.ymd(2022, 3, 1)),
+            Some(Utc.ymd(2022, 3, 8)),
+            InterpolationInterval::Weekly,
+            PriceBasis::Open,
+            InterpolationStrategy::Step,
+        );
+        assert_eq!(
+            get_dates_and_values(&series_entries),
+            vec![((3, 1), 12_500_000, true), ((3, 8), 12_500_000, false),]
+        );
+    }
+
+    #[test]
+    fn returns_empty_baskets_if_any_item_is_missing() {
+        let bpi_engine = BPIEngine::from_test_data(
+            &get_monthly_prices("701111", 1, 6, |_| 0.5),
+            &[],
+            &get_btc_prices(),
+        );
+        let get_basket_series_data = |item_codes: &[&str], area_code: &str| {
+            bpi_engine.get_basket_series_data(
+                &item_codes
+                    .iter()
+                    .map(|item_code| BasketItem {
+                        item_code: ItemCode::new(item_code),
+                        quantity: 1.0,
+                    })
+                    .collect::<Vec<BasketItem>>(),
+                AreaCode::new(area_code),
+                None,
+                None,
+                InterpolationInterval::Monthly,
+                PriceBasis::Open,
+                InterpolationStrategy::Linear,
+            )
+        };
+
+        assert_eq!(get_basket_series_data(&["701111"], "0000").len(), 6);
+        assert!(get_basket_series_data(&["701111", "708111"], "0000").is_empty());
+        assert!(get_basket_series_data(&["701111"], "0100").is_empty());
+        assert!(get_basket_series_data(&[], "0000").is_empty());
+    }
+
+    #[test]
+    fn rebases_index_series_to_100() {
+        let index_values: Vec<(&str, &str, i32, u32, f64)> = (1..=12)
+            .map(|month| ("0000", "SA0", 2022, month, 90.0 + 10.0 * month as f64))
+            .collect();
+        let bpi_engine = BPIEngine::from_test_data(&[], &index_values, &get_doubling_btc_prices());
+        let get_index_series_data = |base_date_or| {
+            bpi_engine
+                .get_index_series_data(
+                    ItemCode::new("SA0"),
+                    AreaCode::new("0000"),
+                    base_date_or,
+                    None,
+                    None,
+                    InterpolationInterval::Monthly,
+                    PriceBasis::Open,
+                    InterpolationStrategy::Linear,
+                )
+                .unwrap()
+        };
+
+        // Defaults to rebasing on the first month.
+        let index_entries = get_index_series_data(None);
+        assert_eq!(index_entries.len(), 12);
+        assert_eq!(get_months_and_values(&index_entries)[0], (1, 100.0));
+        assert_eq!(get_months_and_values(&index_entries)[6], (7, 80.0));
+
+        let index_entries = get_index_series_data(Some(Utc.ymd(2022, 7, 1)));
+        assert_eq!(get_months_and_values(&index_entries)[0], (1, 125.0));
+        assert_eq!(get_months_and_values(&index_entries)[6], (7, 100.0));
+
+        assert!(bpi_engine
+            .get_index_series_data(
+                ItemCode::new("SA0"),
+                AreaCode::new("0100"),
+                None,
+                None,
+                None,
+                InterpolationInterval::Monthly,
+                PriceBasis::Open,
+                InterpolationStrategy::Linear,
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn rebases_composite_indices_to_100() {
+        let mut average_prices = get_monthly_prices("701111", 1, 8, |_| 1.0);
+        average_prices.extend(get_monthly_prices("708111", 4, 10, |month| {
+            month as f64 - 3.0
+        }));
+        let bpi_engine =
+            BPIEngine::from_test_data(&average_prices, &[], &get_doubling_btc_prices());
+        let get_composite_index_series_data = |base_date_or, start_or, end_or| {
+            bpi_engine.get_composite_index_series_data(
+                vec![
+                    (ItemCode::new("701111"), 1.0),
+                    (ItemCode::new("708111"), 1.0),
+                    (ItemCode::new("709111"), 1.0),
+                ],
+                AreaCode::new("0000"),
+                base_date_or,
+                start_or,
+                end_or,
+                InterpolationInterval::Monthly,
+                PriceBasis::Open,
+                InterpolationStrategy::Linear,
+            )
+        };
+
+        // Defaults to rebasing on April, the first month with both items. Outside
+        // of April to August, the index only follows the one item that has data,
+        // so it jumps when eggs take over from flour in September.
+        assert_eq!(
+            get_months_and_values(&get_composite_index_series_data(None, None, None)),
+            vec![
+                (1, 100.0),
+                (2, 100.0),
+                (3, 100.0),
+                (4, 100.0),
+                (5, 150.0),
+                (6, 200.0),
+                (7, 125.0),
+                (8, 150.0),
+                (9, 300.0),
+                (10, 350.0),
+            ]
+        );
+
+        assert_eq!(
+            get_months_and_values(&get_composite_index_series_data(
+                Some(Utc.ymd(2022, 7, 1)),
+                Some(Utc.ymd(2022, 7, 1)),
+                Some(Utc.ymd(2022, 8, 1)),
+            )),
+            vec![(7, 100.0), (8, 112.5)]
+        );
+
+        // Items without a price on the base date are left out.
+        assert_eq!(
+            get_months_and_values(&get_composite_index_series_data(
+                Some(Utc.ymd(2022, 2, 1)),
+                Some(Utc.ymd(2022, 2, 1)),
+                Some(Utc.ymd(2022, 10, 1)),
+            )),
+            vec![
+                (2, 100.0),
+                (3, 100.0),
+                (4, 100.0),
+                (5, 100.0),
+                (6, 100.0),
+                (7, 50.0),
+                (8, 50.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn aligns_compared_series_on_shared_dates() {
+        let mut average_prices = get_monthly_prices("701111", 1, 6, |_| 0.5);
+        average_prices.extend(get_monthly_prices("708111", 4, 9, |_| 2.0));
+        let bpi_engine = BPIEngine::from_test_data(&average_prices, &[], &get_btc_prices());
+        let get_comparison_values = |item_codes: &[&str], intersection_only| {
+            let comparison = bpi_engine.get_comparison_series_data(
+                item_codes
+                    .iter()
+                    .map(|item_code| SeriesKey {
+                        item_code: ItemCode::new(item_code),
+                        area_code: AreaCode::new("0000"),
+                    })
+                    .collect(),
+                None,
+                None,
+                InterpolationInterval::Monthly,
+                PriceBasis::Open,
+                InterpolationStrategy::Linear,
+                intersection_only,
+            );
+            comparison
+                .entries
+                .iter()
+                .map(|entry| {
+                    (
+                        entry.month,
+                        entry
+                            .values
+                            .iter()
+                            .map(|value_or| value_or.as_ref().map(|value| value.value_msats))
+                            .collect::<Vec<Option<u64>>>(),
+                    )
+                })
+                .collect::<Vec<(u32, Vec<Option<u64>>)>>()
+        };
+
+        // Series without data on a date, or at all, are `None` there.
+        let comparison_values = get_comparison_values(&["701111", "708111", "709111"], false);
+        assert_eq!(comparison_values.len(), 9);
+        assert_eq!(comparison_values[0], (1, vec![Some(2_500_000), None, None]));
+        assert_eq!(
+            comparison_values[3],
+            (4, vec![Some(2_500_000), Some(10_000_000), None])
+        );
+        assert_eq!(
+            comparison_values[8],
+            (9, vec![None, Some(10_000_000), None])
+        );
+
+        assert_eq!(
+            get_comparison_values(&["701111", "708111"], true)
+                .iter()
+                .map(|(month, _)| *month)
+                .collect::<Vec<u32>>(),
+            vec![4, 5, 6]
+        );
+        assert!(get_comparison_values(&["701111", "708111", "709111"], true).is_empty());
+    }
+
+    #[test]
+    fn looks_up_prices_within_the_valid_range() {
+        let bpi_engine = BPIEngine::from_test_data(
+            &get_monthly_prices("701111", 1, 6, |month| month as f64 / 2.0),
+            &[],
+            &get_btc_prices(),
+        );
+        let get_point_in_time_data = |date| {
+            bpi_engine.get_point_in_time_data(
+                ItemCode::new("701111"),
+                AreaCode::new("0000"),
+                date,
+                PriceBasis::Open,
+                InterpolationStrategy::Linear,
+            )
+        };
+        let get_dates = |known_prices: &[BPIKnownPrice]| {
+            known_prices
+                .iter()
+                .map(|known_price| (known_price.month, known_price.day))
+                .collect::<Vec<(u32, u32)>>()
+        };
+
+        let entry = get_point_in_time_data(Utc.ymd(2022, 3, 1)).unwrap();
+        assert_eq!(entry.value_msats, 7_500_000);
+        assert_eq!((entry.value_usd, entry.btc_price_usd), (1.5, 20000.0));
+        assert!(entry.observed);
+        assert_eq!(get_dates(&entry.cpi_points), vec![(3, 1)]);
+        assert_eq!(get_dates(&entry.btc_points), vec![(3, 1)]);
+
+        // Halfway through April, so halfway between the April and May prices.
+        let entry = get_point_in_time_data(Utc.ymd(2022, 4, 16)).unwrap();
+        assert_eq!(entry.value_msats, 11_250_000);
+        assert!(!entry.observed);
+        assert_eq!(get_dates(&entry.cpi_points), vec![(4, 1), (5, 1)]);
+        assert_eq!(get_dates(&entry.btc_points), vec![(4, 16)]);
+
+        assert!(get_point_in_time_data(Utc.ymd(2021, 12, 31)).is_none());
+        assert!(get_point_in_time_data(Utc.ymd(2022, 6, 2)).is_none());
+        assert!(bpi_engine
+            .get_point_in_time_data(
+                ItemCode::new("701111"),
+                AreaCode::new("0100"),
+                Utc.ymd(2022, 3, 1),
+                PriceBasis::Open,
+                InterpolationStrategy::Linear,
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn only_prices_observed_months() {
+        let mut average_prices = vec![("0000", "701111", 2021, 12, 0.5)];
+        average_prices.extend(get_monthly_prices("701111", 1, 8, |_| 1.0));
+        let bpi_engine =
+            BPIEngine::from_test_data(&average_prices, &[], &get_doubling_btc_prices());
+        let get_observed_series_data = |start_or, end_or| {
+            bpi_engine.get_observed_series_data(
+                ItemCode::new("701111"),
+                AreaCode::new("0000"),
+                start_or,
+                end_or,
+                PriceBasis::Open,
+            )
+        };
+
+        // December 2021 is left out, since there are no BTC prices that month.
+        let series_entries = get_observed_series_data(None, None);
+        assert_eq!(series_entries.len(), 8);
+        assert_eq!(series_entries[0].year, 2022);
+
+        // Dates between observations are never included.
+        assert_eq!(
+            get_dates_and_values(&get_observed_series_data(
+                Some(Utc.ymd(2022, 5, 15)),
+                Some(Utc.ymd(2022, 7, 31)),
+            )),
+            vec![((6, 1), 5_000_000, true), ((7, 1), 2_500_000, true),]
+        );
+    }
 }
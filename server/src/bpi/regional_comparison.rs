@@ -0,0 +1,187 @@
+use super::cpi_ap::{Area, ItemCode};
+use serde::Serialize;
+
+/// The sats price of one item in every area that has data for it, ranked from
+/// cheapest to most expensive.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BPIRegionalComparison {
+    item_code: ItemCode,
+    areas: Vec<BPIRegionalEntry>,
+    /// `None` if no area has data for the item.
+    spread: Option<BPIRegionalSpread>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BPIRegionalEntry {
+    #[serde(flatten)]
+    area: Area,
+    /// 1 for the cheapest area. Areas with the same price share a rank.
+    rank: usize,
+    value_msats: u64,
+    value_usd: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BPIRegionalSpread {
+    min_msats: u64,
+    max_msats: u64,
+    mean_msats: f64,
+    median_msats: f64,
+    standard_deviation_msats: f64,
+    /// How much more the most expensive area costs than the cheapest, as a
+    /// percentage. `None` if the cheapest area costs zero sats.
+    max_over_min_percent: Option<f64>,
+}
+
+/// Ranks each area's price for `item_code`. Each value is a `(value_msats, value_usd)` pair.
+pub fn build_regional_comparison(
+    item_code: ItemCode,
+    area_values: Vec<(Area, u64, f64)>,
+) -> BPIRegionalComparison {
+    let mut area_values = area_values;
+    area_values.sort_by_key(|(_, value_msats, _)| *value_msats);
+
+    let mut areas: Vec<BPIRegionalEntry> = Vec::new();
+    for (i, (area, value_msats, value_usd)) in area_values.into_iter().enumerate() {
+        let rank = match areas.last() {
+            Some(previous_entry) if previous_entry.value_msats == value_msats => {
+                previous_entry.rank
+            }
+            _ => i + 1,
+        };
+        areas.push(BPIRegionalEntry {
+            area,
+            rank,
+            value_msats,
+            value_usd,
+        });
+    }
+
+    let spread = get_spread(&areas);
+
+    BPIRegionalComparison {
+        item_code,
+        areas,
+        spread,
+    }
+}
+
+/// Expects `areas` to be sorted from cheapest to most expensive.
+fn get_spread(areas: &[BPIRegionalEntry]) -> Option<BPIRegionalSpread> {
+    let min_msats = areas.first()?.value_msats;
+    let max_msats = areas.last()?.value_msats;
+
+    let values_msats: Vec<f64> = areas.iter().map(|entry| entry.value_msats as f64).collect();
+    let mean_msats = values_msats.iter().sum::<f64>() / values_msats.len() as f64;
+    // For even lengths, these are the two middle values.
+    let median_msats =
+        (values_msats[(values_msats.len() - 1) / 2] + values_msats[values_msats.len() / 2]) / 2.0;
+    let variance = values_msats
+        .iter()
+        .map(|value_msats| (value_msats - mean_msats).powi(2))
+        .sum::<f64>()
+        / values_msats.len() as f64;
+
+    Some(BPIRegionalSpread {
+        min_msats,
+        max_msats,
+        mean_msats,
+        median_msats,
+        standard_deviation_msats: variance.sqrt(),
+        max_over_min_percent: if min_msats > 0 {
+            Some((max_msats as f64 / min_msats as f64 - 1.0) * 100.0)
+        } else {
+            None
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bpi::cpi_ap::raw::RawArea;
+
+    fn get_comparison(values_msats: &[u64]) -> BPIRegionalComparison {
+        build_regional_comparison(
+            ItemCode::new("701111"),
+            values_msats
+                .iter()
+                .enumerate()
+                .map(|(i, value_msats)| {
+                    let area = Area::new_from_raw(RawArea {
+                        area_code: format!("A{:03}", i),
+                        area_name: format!("Area {}", i),
+                    });
+                    (area, *value_msats, *value_msats as f64 / 1000.0)
+                })
+                .collect(),
+        )
+    }
+
+    fn get_ranked_areas(comparison: &BPIRegionalComparison) -> Vec<(&str, usize, u64)> {
+        comparison
+            .areas
+            .iter()
+            .map(|entry| {
+                (
+                    entry.area.get_area_code().as_str(),
+                    entry.rank,
+                    entry.value_msats,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ranks_ties_together() {
+        let comparison = get_comparison(&[300, 100, 200, 100]);
+        assert_eq!(
+            get_ranked_areas(&comparison),
+            vec![
+                ("A001", 1, 100),
+                ("A003", 1, 100),
+                ("A002", 3, 200),
+                ("A000", 4, 300)
+            ]
+        );
+
+        let spread = comparison.spread.unwrap();
+        assert_eq!((spread.min_msats, spread.max_msats), (100, 300));
+        assert_eq!(spread.mean_msats, 175.0);
+        // The mean of the two middle values.
+        assert_eq!(spread.median_msats, 150.0);
+        assert_eq!(spread.max_over_min_percent, Some(200.0));
+    }
+
+    #[test]
+    fn takes_the_middle_value_as_the_median_of_odd_counts() {
+        let spread = get_comparison(&[100, 400, 100]).spread.unwrap();
+        assert_eq!(spread.mean_msats, 200.0);
+        assert_eq!(spread.median_msats, 100.0);
+        assert!((spread.standard_deviation_msats - 20000f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn handles_one_or_no_areas() {
+        let comparison = get_comparison(&[250]);
+        assert_eq!(get_ranked_areas(&comparison), vec![("A000", 1, 250)]);
+        let spread = comparison.spread.unwrap();
+        assert_eq!((spread.min_msats, spread.max_msats), (250, 250));
+        assert_eq!((spread.mean_msats, spread.median_msats), (250.0, 250.0));
+        assert_eq!(spread.standard_deviation_msats, 0.0);
+        assert_eq!(spread.max_over_min_percent, Some(0.0));
+
+        let comparison = get_comparison(&[]);
+        assert!(comparison.areas.is_empty());
+        assert!(comparison.spread.is_none());
+    }
+
+    #[test]
+    fn leaves_out_the_percentage_over_zero_sats() {
+        let spread = get_comparison(&[0, 100]).spread.unwrap();
+        assert_eq!(spread.max_over_min_percent, None);
+    }
+}
@@ -0,0 +1,56 @@
+use super::cpi_ap::{raw, ItemCode};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Relative importance of a single average price item, as published by the BLS.
+/// Weights are percentages of total consumer expenditures, but only their size
+/// relative to each other matters.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ItemWeight {
+    item_code: ItemCode,
+    weight: f64,
+}
+
+impl ItemWeight {
+    pub fn get_item_code(&self) -> &ItemCode {
+        &self.item_code
+    }
+
+    pub fn get_weight(&self) -> f64 {
+        self.weight
+    }
+}
+
+/// Loads item weights from `CPI-RELATIVE-IMPORTANCE.txt`, a tab-separated file with
+/// `item_code` and `weight` columns. The file isn't embedded in the binary, so no
+/// weights are loaded unless it exists in the data directory. Items with a missing
+/// or non-positive weight are skipped.
+pub fn get_item_weights(data_dir_or: Option<&Path>) -> Result<Vec<ItemWeight>, String> {
+    let file_name = "CPI-RELATIVE-IMPORTANCE.txt";
+    let raw_item_weights: Vec<RawItemWeight> = match raw::read_data_file_or(data_dir_or, file_name)
+    {
+        Some(file_data) => raw::get_data_sheet(file_name, &file_data)?,
+        None => Vec::new(),
+    };
+
+    Ok(raw_item_weights
+        .into_iter()
+        .filter_map(|raw_item_weight| {
+            let weight = raw_item_weight.weight.parse::<f64>().ok()?;
+            if !weight.is_finite() || weight <= 0.0 {
+                return None;
+            }
+
+            Some(ItemWeight {
+                item_code: ItemCode::new(&raw_item_weight.item_code),
+                weight,
+            })
+        })
+        .collect())
+}
+
+#[derive(Deserialize)]
+struct RawItemWeight {
+    item_code: String,
+    weight: String,
+}
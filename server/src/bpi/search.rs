@@ -0,0 +1,355 @@
+use super::cpi_ap::{Area, Item};
+use super::BPISeriesRange;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Scores for how well a query token matches a name token. A query token's score is
+/// the best score it gets against any token in the item or area name.
+const EXACT_MATCH_SCORE: u32 = 4;
+const PREFIX_MATCH_SCORE: u32 = 3;
+const FUZZY_MATCH_SCORE: u32 = 1;
+
+/// Searches never return more results than this, whatever limit is asked for.
+const MAX_RESULTS: usize = 100;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult {
+    #[serde(flatten)]
+    item: Item,
+    #[serde(flatten)]
+    area: Area,
+    score: u32,
+}
+
+/// Searches item and area names for every item/area combo in `valid_series_ranges`.
+/// Every word in the query has to match either the item or the area, allowing for
+/// typos and different spellings of units. Results are sorted from best to worst.
+pub fn search_series(
+    query: &str,
+    items: &[Item],
+    areas: &[Area],
+    valid_series_ranges: &[BPISeriesRange],
+    limit: usize,
+) -> Vec<SearchResult> {
+    let query_tokens = tokenize(query);
+    if query_tokens.is_empty() {
+        return Vec::new();
+    }
+
+    // Scores each item and area against every query token up front, since
+    // each one shows up in many combos.
+    let item_scores: HashMap<&str, (&Item, Vec<u32>)> = items
+        .iter()
+        .map(|item| {
+            let mut name_tokens = tokenize(item.get_item_name());
+            name_tokens.push(item.get_item_code().as_str().to_lowercase());
+            (
+                item.get_item_code().as_str(),
+                (item, get_token_scores(&query_tokens, &name_tokens)),
+            )
+        })
+        .collect();
+    let area_scores: HashMap<&str, (&Area, Vec<u32>)> = areas
+        .iter()
+        .map(|area| {
+            let mut name_tokens = tokenize(area.get_area_name());
+            name_tokens.push(area.get_area_code().as_str().to_lowercase());
+            (
+                area.get_area_code().as_str(),
+                (area, get_token_scores(&query_tokens, &name_tokens)),
+            )
+        })
+        .collect();
+
+    let mut results: Vec<SearchResult> = valid_series_ranges
+        .iter()
+        .filter_map(|series_range| {
+            let (item, item_token_scores) = item_scores.get(series_range.item_code.as_str())?;
+            let (area, area_token_scores) = area_scores.get(series_range.area_code.as_str())?;
+
+            let mut score = 0;
+            for (item_token_score, area_token_score) in
+                item_token_scores.iter().zip(area_token_scores)
+            {
+                let token_score = std::cmp::max(*item_token_score, *area_token_score);
+                if token_score == 0 {
+                    return None;
+                }
+                score += token_score;
+            }
+
+            Some(SearchResult {
+                item: (*item).clone(),
+                area: (*area).clone(),
+                score,
+            })
+        })
+        .collect();
+
+    results.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| {
+                a.item
+                    .get_item_code()
+                    .as_str()
+                    .cmp(b.item.get_item_code().as_str())
+            })
+            .then_with(|| {
+                a.area
+                    .get_area_code()
+                    .as_str()
+                    .cmp(b.area.get_area_code().as_str())
+            })
+    });
+    results.truncate(std::cmp::min(limit, MAX_RESULTS));
+    results
+}
+
+/// Returns the best score for each query token against any of the name tokens.
+fn get_token_scores(query_tokens: &[String], name_tokens: &[String]) -> Vec<u32> {
+    query_tokens
+        .iter()
+        .map(|query_token| {
+            name_tokens
+                .iter()
+                .map(|name_token| get_token_score(query_token, name_token))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+fn get_token_score(query_token: &str, name_token: &str) -> u32 {
+    if query_token == name_token {
+        return EXACT_MATCH_SCORE;
+    }
+    // Short prefixes like "a" would match almost everything.
+    if query_token.len() >= 2 && name_token.starts_with(query_token) {
+        return PREFIX_MATCH_SCORE;
+    }
+
+    // Allow one typo in short words, and two in longer ones. Numbers have to match
+    // exactly, since item codes that are one digit apart are unrelated.
+    let max_typos = match query_token.len() {
+        _ if query_token.chars().all(|c| c.is_ascii_digit()) => 0,
+        0..=2 => 0,
+        3..=7 => 1,
+        _ => 2,
+    };
+    if max_typos > 0 && get_edit_distance(query_token, name_token) <= max_typos {
+        return FUZZY_MATCH_SCORE;
+    }
+
+    0
+}
+
+/// Splits text into lowercase alphanumeric words, normalizing units so that e.g.
+/// "lb", "lbs" and "pound" are treated as the same word.
+fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| normalize_unit(token).to_string())
+        .collect()
+}
+
+fn normalize_unit(token: &str) -> &str {
+    match token {
+        "lb" | "lbs" | "pound" | "pounds" => "lb",
+        "oz" | "ounce" | "ounces" => "oz",
+        "gal" | "gallon" | "gallons" => "gal",
+        "doz" | "dozen" | "dozens" => "doz",
+        "l" | "lit" | "liter" | "liters" | "litre" | "litres" => "l",
+        "ml" | "milliliter" | "milliliters" | "millilitre" | "millilitres" => "ml",
+        "g" | "gm" | "gram" | "grams" => "g",
+        "kwh" | "kilowatt" => "kwh",
+        "therm" | "therms" => "therm",
+        _ => token,
+    }
+}
+
+/// Levenshtein distance between two words.
+fn get_edit_distance(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut previous_row: Vec<usize> = (0..=b_chars.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current_row = vec![i + 1];
+        for (j, b_char) in b_chars.iter().enumerate() {
+            let substitution_cost = if a_char == *b_char { 0 } else { 1 };
+            current_row.push(
+                (previous_row[j] + substitution_cost)
+                    .min(previous_row[j + 1] + 1)
+                    .min(current_row[j] + 1),
+            );
+        }
+        previous_row = current_row;
+    }
+
+    previous_row[b_chars.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bpi::cpi_ap::raw::{RawArea, RawItem};
+    use crate::bpi::cpi_ap::{AreaCode, ItemCode};
+
+    fn get_items(item_names: &[(&str, &str)]) -> Vec<Item> {
+        item_names
+            .iter()
+            .map(|(item_code, item_name)| {
+                Item::new_from_raw(RawItem {
+                    item_code: item_code.to_string(),
+                    item_name: item_name.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    fn get_areas(area_names: &[(&str, &str)]) -> Vec<Area> {
+        area_names
+            .iter()
+            .map(|(area_code, area_name)| {
+                Area::new_from_raw(RawArea {
+                    area_code: area_code.to_string(),
+                    area_name: area_name.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    fn get_series_ranges(item_and_area_codes: &[(&str, &str)]) -> Vec<BPISeriesRange> {
+        item_and_area_codes
+            .iter()
+            .map(|(item_code, area_code)| BPISeriesRange {
+                item_code: ItemCode::new(item_code),
+                area_code: AreaCode::new(area_code),
+                start_year: 2020,
+                start_month: 1,
+                end_year: 2023,
+                end_month: 12,
+            })
+            .collect()
+    }
+
+    fn get_results(search_results: &[SearchResult]) -> Vec<(&str, &str, u32)> {
+        search_results
+            .iter()
+            .map(|search_result| {
+                (
+                    search_result.item.get_item_code().as_str(),
+                    search_result.area.get_area_code().as_str(),
+                    search_result.score,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scores_exact_then_prefix_then_fuzzy_matches() {
+        let items = get_items(&[
+            ("1", "Apples, per lb."),
+            ("2", "Apple pie, per lb."),
+            ("3", "Apply sauce, per lb."),
+            ("4", "Bananas, per lb."),
+        ]);
+        let areas = get_areas(&[("0000", "U.S. city average"), ("0100", "Northeast")]);
+        let series_ranges = get_series_ranges(&[
+            ("1", "0000"),
+            ("2", "0100"),
+            ("2", "0000"),
+            ("3", "0000"),
+            ("4", "0000"),
+        ]);
+
+        assert_eq!(
+            get_results(&search_series("apple", &items, &areas, &series_ranges, 10)),
+            vec![
+                ("2", "0000", EXACT_MATCH_SCORE),
+                ("2", "0100", EXACT_MATCH_SCORE),
+                ("1", "0000", PREFIX_MATCH_SCORE),
+                ("3", "0000", FUZZY_MATCH_SCORE),
+            ]
+        );
+        // Every word has to match either the item or the area, and units match
+        // however they're spelled.
+        assert_eq!(
+            get_results(&search_series(
+                "pie pounds north",
+                &items,
+                &areas,
+                &series_ranges,
+                10
+            )),
+            vec![("2", "0100", 2 * EXACT_MATCH_SCORE + PREFIX_MATCH_SCORE)]
+        );
+    }
+
+    #[test]
+    fn allows_more_typos_in_longer_words() {
+        assert_eq!(get_token_score("flour", "flour"), EXACT_MATCH_SCORE);
+        assert_eq!(get_token_score("flo", "flour"), PREFIX_MATCH_SCORE);
+        // Single letters would match too much as prefixes.
+        assert_eq!(get_token_score("f", "flour"), 0);
+
+        assert_eq!(get_token_score("flxur", "flour"), FUZZY_MATCH_SCORE);
+        assert_eq!(get_token_score("flxxr", "flour"), 0);
+        assert_eq!(get_token_score("fortifxxd", "fortified"), FUZZY_MATCH_SCORE);
+        assert_eq!(get_token_score("fortxxxed", "fortified"), 0);
+        assert_eq!(get_token_score("ox", "oz"), 0);
+        // Numbers have to match exactly.
+        assert_eq!(get_token_score("701112", "701111"), 0);
+    }
+
+    #[test]
+    fn normalizes_units() {
+        assert_eq!(
+            tokenize("Milk, per 1/2 gal. (1.9 lit)"),
+            vec!["milk", "per", "1", "2", "gal", "1", "9", "l"]
+        );
+        assert_eq!(tokenize("3 Pounds, 2 Litres"), vec!["3", "lb", "2", "l"]);
+    }
+
+    #[test]
+    fn only_returns_series_with_a_valid_range() {
+        let items = get_items(&[("1", "Apples, per lb."), ("2", "Apple pie, per lb.")]);
+        let areas = get_areas(&[("0000", "U.S. city average")]);
+        let series_ranges = get_series_ranges(&[("1", "0000")]);
+
+        assert_eq!(
+            get_results(&search_series("apple", &items, &areas, &series_ranges, 10)),
+            vec![("1", "0000", PREFIX_MATCH_SCORE)]
+        );
+    }
+
+    #[test]
+    fn limits_results() {
+        let items = get_items(&[("1", "Apples, per lb.")]);
+        let area_names: Vec<(String, String)> = (0..150)
+            .map(|i| (format!("{:04}", i), format!("Area {}", i)))
+            .collect();
+        let area_names: Vec<(&str, &str)> = area_names
+            .iter()
+            .map(|(area_code, area_name)| (area_code.as_str(), area_name.as_str()))
+            .collect();
+        let areas = get_areas(&area_names);
+        let item_and_area_codes: Vec<(&str, &str)> = area_names
+            .iter()
+            .map(|(area_code, _)| ("1", *area_code))
+            .collect();
+        let series_ranges = get_series_ranges(&item_and_area_codes);
+
+        assert_eq!(
+            search_series("apples", &items, &areas, &series_ranges, 3).len(),
+            3
+        );
+        assert_eq!(
+            search_series("apples", &items, &areas, &series_ranges, usize::MAX).len(),
+            MAX_RESULTS
+        );
+        assert!(search_series("", &items, &areas, &series_ranges, 10).is_empty());
+    }
+}
@@ -0,0 +1,223 @@
+use super::BPISeriesEntry;
+
+/// Ways of smoothing out BTC volatility in a BPI series. Each method looks at
+/// `window` entries at a time, so the window is measured in entries rather than
+/// days and depends on the series' interval.
+#[derive(Clone, Copy)]
+pub enum SmoothingMethod {
+    /// Mean of the current entry and the `window - 1` entries before it.
+    SimpleMovingAverage,
+    /// Exponentially-weighted mean, with a smoothing factor of `2 / (window + 1)`.
+    ExponentialMovingAverage,
+    /// Median of the `window` entries centered on the current entry.
+    CenteredMedian,
+}
+
+impl<'a> rocket::form::FromFormField<'a> for SmoothingMethod {
+    fn from_value(field: rocket::form::ValueField<'a>) -> rocket::form::Result<'a, Self> {
+        match field.value {
+            "sma" => Ok(Self::SimpleMovingAverage),
+            "ema" => Ok(Self::ExponentialMovingAverage),
+            "median" => Ok(Self::CenteredMedian),
+            _ => Err(rocket::form::Error::validation(format!(
+                "Unknown smoothing method: {}",
+                field.value
+            ))
+            .into()),
+        }
+    }
+}
+
+/// Smooths both the sats and dollar values of a chronologically sorted series.
+/// Every entry is kept, so windows are truncated near the ends of the series.
+/// Smoothed entries mix in their neighbours, so they're no longer marked as
+/// observed unless the window is a single entry.
+pub fn smooth_series(
+    series_entries: &[BPISeriesEntry],
+    smoothing_method: SmoothingMethod,
+    window: usize,
+) -> Vec<BPISeriesEntry> {
+    let window = std::cmp::max(window, 1);
+
+    let values_msats: Vec<f64> = series_entries
+        .iter()
+        .map(|entry| entry.value_msats)
+        .collect();
+    let smoothed_values_msats = smooth_values(&values_msats, smoothing_method, window);
+
+    // Dollar values are either set for every entry or for none of them.
+    let smoothed_values_usd_or = series_entries
+        .iter()
+        .map(|entry| entry.value_usd)
+        .collect::<Option<Vec<f64>>>()
+        .map(|values_usd| smooth_values(&values_usd, smoothing_method, window));
+
+    series_entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| BPISeriesEntry {
+            value_msats: smoothed_values_msats[i],
+            value_usd: smoothed_values_usd_or
+                .as_ref()
+                .map(|smoothed_values_usd| smoothed_values_usd[i]),
+            observed: entry.observed && window == 1,
+            ..entry.clone()
+        })
+        .collect()
+}
+
+pub(super) fn smooth_values(
+    values: &[f64],
+    smoothing_method: SmoothingMethod,
+    window: usize,
+) -> Vec<f64> {
+    match smoothing_method {
+        SmoothingMethod::SimpleMovingAverage => {
+            let mut smoothed_values = Vec::with_capacity(values.len());
+            let mut window_total = 0.0;
+            for (i, value) in values.iter().enumerate() {
+                window_total += value;
+                if i >= window {
+                    window_total -= values[i - window];
+                }
+                smoothed_values.push(window_total / std::cmp::min(i + 1, window) as f64);
+            }
+            smoothed_values
+        }
+        SmoothingMethod::ExponentialMovingAverage => {
+            let smoothing_factor = 2.0 / (window as f64 + 1.0);
+            let mut smoothed_values: Vec<f64> = Vec::with_capacity(values.len());
+            for value in values {
+                let smoothed_value = match smoothed_values.last() {
+                    Some(previous_value) => {
+                        smoothing_factor * value + (1.0 - smoothing_factor) * previous_value
+                    }
+                    // Seed the average with the first value.
+                    None => *value,
+                };
+                smoothed_values.push(smoothed_value);
+            }
+            smoothed_values
+        }
+        SmoothingMethod::CenteredMedian => {
+            let half_window = window / 2;
+            (0..values.len())
+                .map(|i| {
+                    // Even windows lean one entry towards the past.
+                    let start = i.saturating_sub(half_window);
+                    let end = std::cmp::min(i + (window - 1 - half_window) + 1, values.len());
+                    let mut window_values = values[start..end].to_vec();
+                    window_values.sort_by(|a, b| a.total_cmp(b));
+
+                    // For even lengths, these are the two middle values.
+                    let lower_middle = window_values[(window_values.len() - 1) / 2];
+                    let upper_middle = window_values[window_values.len() / 2];
+                    (lower_middle + upper_middle) / 2.0
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_values(values: Vec<f64>, expected_values: &[f64]) {
+        assert_eq!(values.len(), expected_values.len());
+        for (value, expected_value) in values.iter().zip(expected_values) {
+            assert!(
+                (value - expected_value).abs() < 1e-9,
+                "expected {:?}, got {:?}",
+                expected_values,
+                values
+            );
+        }
+    }
+
+    const VALUES: [f64; 5] = [1.0, 5.0, 3.0, 10.0, 4.0];
+
+    #[test]
+    fn smooths_with_simple_moving_average() {
+        // The first entries average over however many entries there are so far.
+        assert_values(
+            smooth_values(&VALUES, SmoothingMethod::SimpleMovingAverage, 3),
+            &[1.0, 3.0, 3.0, 6.0, 17.0 / 3.0],
+        );
+    }
+
+    #[test]
+    fn smooths_with_exponential_moving_average() {
+        // A window of 3 gives a smoothing factor of 0.5.
+        assert_values(
+            smooth_values(&VALUES, SmoothingMethod::ExponentialMovingAverage, 3),
+            &[1.0, 3.0, 3.0, 6.5, 5.25],
+        );
+    }
+
+    #[test]
+    fn smooths_with_centered_median() {
+        // Windows are truncated at both ends, and even-length windows use the
+        // mean of the two middle values.
+        assert_values(
+            smooth_values(&VALUES, SmoothingMethod::CenteredMedian, 3),
+            &[3.0, 3.0, 5.0, 4.0, 7.0],
+        );
+        // Even windows lean one entry towards the past.
+        assert_values(
+            smooth_values(&VALUES, SmoothingMethod::CenteredMedian, 4),
+            &[3.0, 3.0, 4.0, 4.5, 4.0],
+        );
+    }
+
+    #[test]
+    fn leaves_values_alone_with_a_window_of_one() {
+        for smoothing_method in [
+            SmoothingMethod::SimpleMovingAverage,
+            SmoothingMethod::ExponentialMovingAverage,
+            SmoothingMethod::CenteredMedian,
+        ] {
+            assert_values(smooth_values(&VALUES, smoothing_method, 1), &VALUES);
+        }
+    }
+
+    #[test]
+    fn smooths_unrounded_values_and_clears_observed() {
+        let series_entries: Vec<BPISeriesEntry> = [1.25, 1.25, 2.25]
+            .iter()
+            .enumerate()
+            .map(|(i, value_msats)| BPISeriesEntry {
+                year: 2020,
+                month: 1,
+                day: i as u32 + 1,
+                value_msats: *value_msats,
+                value_usd: Some(*value_msats * 4.0),
+                observed: i != 1,
+            })
+            .collect();
+
+        let smoothed_entries =
+            smooth_series(&series_entries, SmoothingMethod::SimpleMovingAverage, 2);
+        let values: Vec<(f64, Option<f64>, bool)> = smoothed_entries
+            .iter()
+            .map(|entry| (entry.value_msats, entry.value_usd, entry.observed))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                (1.25, Some(5.0), false),
+                (1.25, Some(5.0), false),
+                // Would be 1.5 if the values were rounded first.
+                (1.75, Some(7.0), false)
+            ]
+        );
+
+        let unsmoothed_entries =
+            smooth_series(&series_entries, SmoothingMethod::SimpleMovingAverage, 1);
+        let observed: Vec<bool> = unsmoothed_entries
+            .iter()
+            .map(|entry| entry.observed)
+            .collect();
+        assert_eq!(observed, vec![true, false, true]);
+    }
+}
@@ -0,0 +1,298 @@
+use super::btc_price_history::BTCPriceColumns;
+use super::cpi_query_engine::CpiQueryEngine;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+/// Bumped whenever the layout of `Snapshot` or anything it contains changes,
+/// so that snapshots written by older servers are ignored rather than misread.
+const SNAPSHOT_FORMAT_VERSION: u32 = 3;
+
+/// Every file that `CpiQueryEngine` reads from the CPI data directory.
+const CPI_DATA_FILE_NAMES: &[&str] = &[
+    "CPI-AREAS.txt",
+    "CPI-ITEMS.txt",
+    "CPI-TIME-SERIES.txt",
+    "CU-AREAS.txt",
+    "CU-ITEMS.txt",
+    "CU-TIME-SERIES.txt",
+    "CPI-RELATIVE-IMPORTANCE.txt",
+];
+
+/// Written at the start of every snapshot. It's read on its own before the rest of
+/// the snapshot, so incompatible snapshots are rejected without decoding them.
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+struct SnapshotHeader {
+    format_version: u32,
+    server_version: String,
+    /// Hash of everything the snapshot was built from. See `get_source_fingerprint`.
+    source_fingerprint: u64,
+}
+
+impl SnapshotHeader {
+    fn new(cpi_data_dir_or: Option<&Path>, btc_provider_names: &[String]) -> Self {
+        Self {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            server_version: env!("CARGO_PKG_VERSION").to_string(),
+            source_fingerprint: get_source_fingerprint(cpi_data_dir_or, btc_provider_names),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    header: SnapshotHeader,
+    pub cpi_query_engine: CpiQueryEngine,
+    pub cpi_index_query_engine: CpiQueryEngine,
+    /// Price data for each BTC price provider, in priority order.
+    pub btc_price_columns: Vec<Option<BTCPriceColumns>>,
+}
+
+/// Reads the snapshot at `path`, returning an error if it can't be read or wasn't
+/// built from the current CPI data files, BTC price providers and server binary.
+pub fn read_snapshot(
+    path: &Path,
+    cpi_data_dir_or: Option<&Path>,
+    btc_provider_names: &[String],
+) -> Result<Snapshot, Box<dyn std::error::Error>> {
+    let snapshot_bytes = std::fs::read(path)?;
+    let bincode_options = get_bincode_options(snapshot_bytes.len() as u64);
+
+    let header: SnapshotHeader = bincode_options.deserialize(&snapshot_bytes)?;
+    if header.format_version != SNAPSHOT_FORMAT_VERSION {
+        return Err(Box::from(format!(
+            "Unsupported snapshot format version {}",
+            header.format_version
+        )));
+    }
+    if header != SnapshotHeader::new(cpi_data_dir_or, btc_provider_names) {
+        return Err(Box::from("Snapshot is out of date"));
+    }
+
+    Ok(bincode_options.deserialize(&snapshot_bytes)?)
+}
+
+/// The same encoding as `bincode::serialize`, but nothing can be decoded from more
+/// than `limit` bytes. Otherwise a corrupt length could make decoding allocate far
+/// more memory than the snapshot itself takes up.
+fn get_bincode_options(limit: u64) -> impl Options + Copy {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(limit)
+}
+
+/// Writes a snapshot to `path`. The snapshot is written to a temporary file first
+/// and then moved into place, so a partially-written snapshot is never read.
+pub fn write_snapshot(
+    path: &Path,
+    cpi_data_dir_or: Option<&Path>,
+    btc_provider_names: &[String],
+    cpi_query_engine: &CpiQueryEngine,
+    cpi_index_query_engine: &CpiQueryEngine,
+    btc_price_columns: Vec<Option<&BTCPriceColumns>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Serialized with borrowed fields, which bincode encodes the same way as
+    // the owned fields of `Snapshot`.
+    #[derive(Serialize)]
+    struct SnapshotRef<'a> {
+        header: SnapshotHeader,
+        cpi_query_engine: &'a CpiQueryEngine,
+        cpi_index_query_engine: &'a CpiQueryEngine,
+        btc_price_columns: Vec<Option<&'a BTCPriceColumns>>,
+    }
+
+    let snapshot_bytes = bincode::serialize(&SnapshotRef {
+        header: SnapshotHeader::new(cpi_data_dir_or, btc_provider_names),
+        cpi_query_engine,
+        cpi_index_query_engine,
+        btc_price_columns,
+    })?;
+
+    let temp_path = path.with_extension("tmp");
+    std::fs::write(&temp_path, snapshot_bytes)?;
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+/// Hashes the size and modification time of the server binary, which the default
+/// CPI and BTC price data are embedded in, and of each CPI data file. Also covers
+/// the data directory itself and the BTC price providers, since either can be
+/// changed through environment variables between runs.
+fn get_source_fingerprint(cpi_data_dir_or: Option<&Path>, btc_provider_names: &[String]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    if let Ok(exe_path) = std::env::current_exe() {
+        hash_file_metadata(&exe_path, &mut hasher);
+    }
+
+    cpi_data_dir_or.hash(&mut hasher);
+    if let Some(cpi_data_dir) = cpi_data_dir_or {
+        for file_name in CPI_DATA_FILE_NAMES {
+            hash_file_metadata(&cpi_data_dir.join(file_name), &mut hasher);
+        }
+    }
+
+    btc_provider_names.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+fn hash_file_metadata(path: &Path, hasher: &mut DefaultHasher) {
+    match std::fs::metadata(path) {
+        Ok(metadata) => {
+            metadata.len().hash(hasher);
+            metadata.modified().ok().hash(hasher);
+        }
+        // Files that don't exist are hashed too, so that adding one is noticed.
+        Err(_) => "missing".hash(hasher),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bpi::cpi_ap::{AreaCode, ItemCode, SeriesEntry};
+    use crate::bpi::cpi_query_engine::CpiDataset;
+    use chrono::{TimeZone, Utc};
+    use std::path::PathBuf;
+
+    fn get_temp_dir(test_name: &str) -> PathBuf {
+        let temp_dir = std::env::temp_dir().join(format!(
+            "satdash-snapshot-{}-{}",
+            test_name,
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+        temp_dir
+    }
+
+    fn get_provider_names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn write_test_snapshot(path: &Path, cpi_data_dir_or: Option<&Path>, provider_names: &[&str]) {
+        let cpi_query_engine = CpiQueryEngine::from_series_entries(
+            CpiDataset::AveragePrice,
+            vec![SeriesEntry::new("0000", "701111", 2023, 1, 0.5)],
+        );
+        let cpi_index_query_engine = CpiQueryEngine::from_series_entries(
+            CpiDataset::Index,
+            vec![SeriesEntry::new("0000", "SA0", 2023, 1, 300.0)],
+        );
+        write_snapshot(
+            path,
+            cpi_data_dir_or,
+            &get_provider_names(provider_names),
+            &cpi_query_engine,
+            &cpi_index_query_engine,
+            vec![None],
+        )
+        .unwrap();
+    }
+
+    fn get_read_error(
+        path: &Path,
+        cpi_data_dir_or: Option<&Path>,
+        provider_names: &[&str],
+    ) -> String {
+        read_snapshot(path, cpi_data_dir_or, &get_provider_names(provider_names))
+            .err()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn reads_written_snapshots() {
+        let temp_dir = get_temp_dir("round-trip");
+        let path = temp_dir.join("snapshot.bin");
+        write_test_snapshot(&path, None, &["coingecko"]);
+
+        let snapshot = read_snapshot(&path, None, &get_provider_names(&["coingecko"])).unwrap();
+        std::fs::remove_dir_all(&temp_dir).unwrap();
+
+        let get_value = |cpi_query_engine: &CpiQueryEngine, item_code: &str| {
+            cpi_query_engine
+                .get_series_data(ItemCode::new(item_code), AreaCode::new("0000"))?
+                .get_price(Utc.ymd(2023, 1, 1))
+        };
+        assert_eq!(get_value(&snapshot.cpi_query_engine, "701111"), Some(0.5));
+        assert_eq!(
+            get_value(&snapshot.cpi_index_query_engine, "SA0"),
+            Some(300.0)
+        );
+        assert_eq!(snapshot.btc_price_columns.len(), 1);
+        assert!(snapshot.btc_price_columns[0].is_none());
+    }
+
+    #[test]
+    fn rejects_other_format_versions() {
+        let temp_dir = get_temp_dir("format-version");
+        let path = temp_dir.join("snapshot.bin");
+        let mut header = SnapshotHeader::new(None, &get_provider_names(&["coingecko"]));
+        header.format_version = SNAPSHOT_FORMAT_VERSION - 1;
+        std::fs::write(&path, bincode::serialize(&header).unwrap()).unwrap();
+
+        let error = get_read_error(&path, None, &["coingecko"]);
+        std::fs::remove_dir_all(&temp_dir).unwrap();
+
+        assert_eq!(
+            error,
+            format!(
+                "Unsupported snapshot format version {}",
+                SNAPSHOT_FORMAT_VERSION - 1
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_snapshots_built_from_other_sources() {
+        let temp_dir = get_temp_dir("sources");
+        let path = temp_dir.join("snapshot.bin");
+        let cpi_data_dir = temp_dir.join("cpi");
+        std::fs::create_dir_all(&cpi_data_dir).unwrap();
+        std::fs::write(cpi_data_dir.join("CPI-ITEMS.txt"), "item_code\titem_name\n").unwrap();
+        write_test_snapshot(&path, Some(&cpi_data_dir), &["coingecko", "csv"]);
+
+        let provider_error = get_read_error(&path, Some(&cpi_data_dir), &["csv", "coingecko"]);
+        let data_dir_error = get_read_error(&path, None, &["coingecko", "csv"]);
+        std::fs::write(
+            cpi_data_dir.join("CPI-ITEMS.txt"),
+            "item_code\titem_name\nSA0\tAll items\n",
+        )
+        .unwrap();
+        let data_file_error = get_read_error(&path, Some(&cpi_data_dir), &["coingecko", "csv"]);
+        std::fs::remove_dir_all(&temp_dir).unwrap();
+
+        assert_eq!(provider_error, "Snapshot is out of date");
+        assert_eq!(data_dir_error, "Snapshot is out of date");
+        assert_eq!(data_file_error, "Snapshot is out of date");
+    }
+
+    #[test]
+    fn fails_to_read_truncated_or_corrupt_snapshots() {
+        let temp_dir = get_temp_dir("corrupt");
+        let path = temp_dir.join("snapshot.bin");
+        write_test_snapshot(&path, None, &["coingecko"]);
+        let snapshot_bytes = std::fs::read(&path).unwrap();
+
+        std::fs::write(&path, &snapshot_bytes[..snapshot_bytes.len() - 1]).unwrap();
+        let truncated_result = read_snapshot(&path, None, &get_provider_names(&["coingecko"]));
+
+        // The server version's length comes right after the format version.
+        let mut corrupt_bytes = snapshot_bytes;
+        corrupt_bytes[4..12].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(&path, &corrupt_bytes).unwrap();
+        let corrupt_result = read_snapshot(&path, None, &get_provider_names(&["coingecko"]));
+
+        std::fs::remove_file(&path).unwrap();
+        let missing_result = read_snapshot(&path, None, &get_provider_names(&["coingecko"]));
+        std::fs::remove_dir_all(&temp_dir).unwrap();
+
+        assert!(truncated_result.is_err());
+        assert!(corrupt_result.is_err());
+        assert!(missing_result.is_err());
+    }
+}
@@ -0,0 +1,320 @@
+use super::BPISeriesEntry;
+use chrono::{TimeZone, Utc};
+use serde::Serialize;
+
+/// Summary of how an item's sats price moved over a BPI series. All percentages are
+/// changes in the sats price, so negative values mean bitcoin bought more of the item.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BPISeriesStatistics {
+    start: BPIStatisticsPoint,
+    end: BPIStatisticsPoint,
+    percent_change: f64,
+    /// Compound annual rate of change. `None` if the series doesn't span
+    /// more than one day.
+    annualized_percent_change: Option<f64>,
+    /// The month whose sats price fell the most, i.e. the biggest gain in
+    /// purchasing power. `None` if the series doesn't span two months.
+    best_month: Option<BPIMonthlyChange>,
+    /// The month whose sats price rose the most.
+    worst_month: Option<BPIMonthlyChange>,
+    all_time_low: BPIStatisticsPoint,
+    /// Largest loss of purchasing power, measured from the lowest sats price so far
+    /// to a later high. `None` if the sats price never rose above a previous low.
+    max_drawdown: Option<BPIDrawdown>,
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct BPIStatisticsPoint {
+    year: i32,
+    month: u32,
+    day: u32,
+    value_msats: u64,
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct BPIMonthlyChange {
+    year: i32,
+    month: u32,
+    /// Change from the last value of the previous month to the last value of this one.
+    percent_change: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BPIDrawdown {
+    /// Lost purchasing power, from 0 to 100.
+    percent: f64,
+    /// Where purchasing power peaked, which is where the sats price was lowest.
+    peak: BPIStatisticsPoint,
+    /// Where purchasing power bottomed out, which is where the sats price was highest.
+    trough: BPIStatisticsPoint,
+}
+
+/// Computes statistics over a chronologically sorted series. Returns `None` for
+/// an empty series, or if the series starts at zero sats.
+pub fn get_series_statistics(series_entries: &[BPISeriesEntry]) -> Option<BPISeriesStatistics> {
+    let points: Vec<BPIStatisticsPoint> = series_entries
+        .iter()
+        .map(|entry| BPIStatisticsPoint {
+            year: entry.year,
+            month: entry.month,
+            day: entry.day,
+            value_msats: entry.get_value_msats(),
+        })
+        .collect();
+
+    let start = *points.first()?;
+    let end = *points.last()?;
+    if start.value_msats == 0 {
+        return None;
+    }
+
+    let percent_change = get_percent_change(start.value_msats, end.value_msats);
+
+    let days = (Utc.ymd(end.year, end.month, end.day)
+        - Utc.ymd(start.year, start.month, start.day))
+    .num_days();
+    let annualized_percent_change = if days > 0 {
+        let growth = end.value_msats as f64 / start.value_msats as f64;
+        Some((growth.powf(365.25 / days as f64) - 1.0) * 100.0)
+    } else {
+        None
+    };
+
+    let monthly_changes = get_monthly_changes(&points);
+    let best_month = monthly_changes
+        .iter()
+        .min_by(|a, b| a.percent_change.total_cmp(&b.percent_change))
+        .copied();
+    let worst_month = monthly_changes
+        .iter()
+        .max_by(|a, b| a.percent_change.total_cmp(&b.percent_change))
+        .copied();
+
+    // The earliest point wins if the low is hit more than once.
+    let mut all_time_low = start;
+    for point in &points {
+        if point.value_msats < all_time_low.value_msats {
+            all_time_low = *point;
+        }
+    }
+
+    Some(BPISeriesStatistics {
+        start,
+        end,
+        percent_change,
+        annualized_percent_change,
+        best_month,
+        worst_month,
+        all_time_low,
+        max_drawdown: get_max_drawdown(&points),
+    })
+}
+
+/// Uses the last point in each calendar month as that month's value.
+fn get_monthly_changes(points: &[BPIStatisticsPoint]) -> Vec<BPIMonthlyChange> {
+    let mut month_end_points: Vec<BPIStatisticsPoint> = Vec::new();
+    for point in points {
+        match month_end_points.last_mut() {
+            Some(last_point)
+                if last_point.year == point.year && last_point.month == point.month =>
+            {
+                *last_point = *point
+            }
+            _ => month_end_points.push(*point),
+        }
+    }
+
+    month_end_points
+        .windows(2)
+        .filter(|window| window[0].value_msats > 0)
+        .map(|window| BPIMonthlyChange {
+            year: window[1].year,
+            month: window[1].month,
+            percent_change: get_percent_change(window[0].value_msats, window[1].value_msats),
+        })
+        .collect()
+}
+
+fn get_max_drawdown(points: &[BPIStatisticsPoint]) -> Option<BPIDrawdown> {
+    let mut max_drawdown_or: Option<BPIDrawdown> = None;
+    let mut peak = *points.first()?;
+
+    for point in points {
+        if point.value_msats < peak.value_msats {
+            peak = *point;
+            continue;
+        }
+        if point.value_msats == 0 {
+            continue;
+        }
+
+        // Purchasing power is the inverse of the sats price.
+        let percent = (1.0 - peak.value_msats as f64 / point.value_msats as f64) * 100.0;
+        let is_larger = match &max_drawdown_or {
+            Some(max_drawdown) => percent > max_drawdown.percent,
+            None => percent > 0.0,
+        };
+        if is_larger {
+            max_drawdown_or = Some(BPIDrawdown {
+                percent,
+                peak,
+                trough: *point,
+            });
+        }
+    }
+
+    max_drawdown_or
+}
+
+fn get_percent_change(start_value_msats: u64, end_value_msats: u64) -> f64 {
+    (end_value_msats as f64 / start_value_msats as f64 - 1.0) * 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_series_entries(values: &[((i32, u32, u32), u64)]) -> Vec<BPISeriesEntry> {
+        values
+            .iter()
+            .map(|((year, month, day), value_msats)| BPISeriesEntry {
+                year: *year,
+                month: *month,
+                day: *day,
+                value_msats: *value_msats as f64,
+                value_usd: None,
+                observed: true,
+            })
+            .collect()
+    }
+
+    fn get_date_and_value(point: &BPIStatisticsPoint) -> ((i32, u32, u32), u64) {
+        ((point.year, point.month, point.day), point.value_msats)
+    }
+
+    fn assert_close(value: f64, expected_value: f64) {
+        assert!(
+            (value - expected_value).abs() < 1e-9,
+            "expected {}, got {}",
+            expected_value,
+            value
+        );
+    }
+
+    #[test]
+    fn summarizes_a_series() {
+        let statistics = get_series_statistics(&get_series_entries(&[
+            ((2020, 1, 1), 1000),
+            ((2020, 1, 15), 800),
+            ((2020, 2, 1), 1200),
+            ((2020, 2, 20), 900),
+            ((2020, 3, 10), 1800),
+            ((2020, 4, 1), 800),
+            ((2021, 1, 1), 2000),
+        ]))
+        .unwrap();
+
+        assert_eq!(get_date_and_value(&statistics.start), ((2020, 1, 1), 1000));
+        assert_eq!(get_date_and_value(&statistics.end), ((2021, 1, 1), 2000));
+        assert_close(statistics.percent_change, 100.0);
+        // 2020 is a leap year, so the series spans 366 days.
+        assert_close(
+            statistics.annualized_percent_change.unwrap(),
+            (2f64.powf(365.25 / 366.0) - 1.0) * 100.0,
+        );
+
+        // Month-end values are 800, 900, 1800, 800 and 2000.
+        let best_month = statistics.best_month.unwrap();
+        assert_eq!((best_month.year, best_month.month), (2020, 4));
+        assert_close(best_month.percent_change, (800.0 / 1800.0 - 1.0) * 100.0);
+        let worst_month = statistics.worst_month.unwrap();
+        assert_eq!((worst_month.year, worst_month.month), (2021, 1));
+        assert_close(worst_month.percent_change, 150.0);
+
+        // 800 is hit twice, and the earliest one wins.
+        assert_eq!(
+            get_date_and_value(&statistics.all_time_low),
+            ((2020, 1, 15), 800)
+        );
+
+        let max_drawdown = statistics.max_drawdown.unwrap();
+        assert_close(max_drawdown.percent, 60.0);
+        assert_eq!(get_date_and_value(&max_drawdown.peak), ((2020, 1, 15), 800));
+        assert_eq!(
+            get_date_and_value(&max_drawdown.trough),
+            ((2021, 1, 1), 2000)
+        );
+    }
+
+    #[test]
+    fn measures_drawdowns_from_the_low_before_the_high() {
+        // The later low of 500 only recovers to 900, which is a smaller drawdown
+        // than going from 1000 to 2000.
+        let statistics = get_series_statistics(&get_series_entries(&[
+            ((2020, 1, 1), 1000),
+            ((2020, 1, 2), 2000),
+            ((2020, 1, 3), 500),
+            ((2020, 1, 4), 900),
+        ]))
+        .unwrap();
+        let max_drawdown = statistics.max_drawdown.unwrap();
+        assert_close(max_drawdown.percent, 50.0);
+        assert_eq!(get_date_and_value(&max_drawdown.peak), ((2020, 1, 1), 1000));
+        assert_eq!(
+            get_date_and_value(&max_drawdown.trough),
+            ((2020, 1, 2), 2000)
+        );
+
+        // A sats price that only ever falls never loses purchasing power.
+        let statistics = get_series_statistics(&get_series_entries(&[
+            ((2020, 1, 1), 1000),
+            ((2020, 1, 2), 1000),
+            ((2020, 1, 3), 500),
+        ]))
+        .unwrap();
+        assert!(statistics.max_drawdown.is_none());
+    }
+
+    #[test]
+    fn skips_monthly_changes_from_zero() {
+        let statistics = get_series_statistics(&get_series_entries(&[
+            ((2020, 1, 31), 100),
+            ((2020, 2, 29), 0),
+            ((2020, 3, 31), 50),
+        ]))
+        .unwrap();
+
+        // Only February has a change, since March would be from zero.
+        let best_month = statistics.best_month.unwrap();
+        let worst_month = statistics.worst_month.unwrap();
+        assert_eq!((best_month.year, best_month.month), (2020, 2));
+        assert_eq!((worst_month.year, worst_month.month), (2020, 2));
+        assert_close(best_month.percent_change, -100.0);
+    }
+
+    #[test]
+    fn handles_short_series() {
+        assert!(get_series_statistics(&[]).is_none());
+        assert!(get_series_statistics(&get_series_entries(&[
+            ((2020, 1, 1), 0),
+            ((2020, 2, 1), 5)
+        ]))
+        .is_none());
+
+        let statistics =
+            get_series_statistics(&get_series_entries(&[((2020, 1, 1), 1000)])).unwrap();
+        assert_close(statistics.percent_change, 0.0);
+        assert!(statistics.annualized_percent_change.is_none());
+        assert!(statistics.best_month.is_none());
+        assert!(statistics.worst_month.is_none());
+        assert!(statistics.max_drawdown.is_none());
+        assert_eq!(
+            get_date_and_value(&statistics.all_time_low),
+            ((2020, 1, 1), 1000)
+        );
+    }
+}
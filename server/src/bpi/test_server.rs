@@ -0,0 +1,118 @@
+use rocket::tokio::io::{AsyncReadExt, AsyncWriteExt};
+use rocket::tokio::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+/// A request received by a `TestServer`.
+#[derive(Clone)]
+pub struct TestRequest {
+    pub method: String,
+    pub path: String,
+    pub body: String,
+}
+
+type Responder = dyn Fn(&TestRequest) -> (u16, String) + Send + Sync;
+
+/// Minimal HTTP server on localhost for testing HTTP clients against recorded
+/// responses. Every request is answered by `respond`, which returns a status code
+/// and a body, and is recorded so that tests can check what was sent.
+pub struct TestServer {
+    url: String,
+    requests: Arc<Mutex<Vec<TestRequest>>>,
+}
+
+impl TestServer {
+    pub async fn start(
+        respond: impl Fn(&TestRequest) -> (u16, String) + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::from(Mutex::new(Vec::new()));
+        let respond: Arc<Responder> = Arc::from(respond);
+
+        let server_requests = requests.clone();
+        rocket::tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let requests = server_requests.clone();
+                let respond = respond.clone();
+                rocket::tokio::spawn(async move {
+                    handle_connection(stream, requests, respond.as_ref()).await;
+                });
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    /// Serves the same response to every request.
+    pub async fn start_with_response(status: u16, body: &str) -> Self {
+        let body = body.to_string();
+        Self::start(move |_| (status, body.clone())).await
+    }
+
+    /// Returns the URL of `path` on this server, e.g. `get_url("/prices")`.
+    pub fn get_url(&self, path: &str) -> String {
+        format!("{}{}", self.url, path)
+    }
+
+    /// Returns every request received so far, in order.
+    pub fn get_requests(&self) -> Vec<TestRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    requests: Arc<Mutex<Vec<TestRequest>>>,
+    respond: &Responder,
+) {
+    // Reads until the end of the headers, then however much body they announce.
+    let mut request_bytes = Vec::new();
+    let mut buffer = [0; 4096];
+    let header_length = loop {
+        if let Some(index) = find_subslice(&request_bytes, b"\r\n\r\n") {
+            break index + 4;
+        }
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return,
+            Ok(read_length) => request_bytes.extend_from_slice(&buffer[..read_length]),
+        }
+    };
+
+    let headers = String::from_utf8_lossy(&request_bytes[..header_length]).to_string();
+    let content_length = headers
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    while request_bytes.len() < header_length + content_length {
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return,
+            Ok(read_length) => request_bytes.extend_from_slice(&buffer[..read_length]),
+        }
+    }
+
+    let mut request_line_parts = headers.lines().next().unwrap_or_default().split(' ');
+    let request = TestRequest {
+        method: request_line_parts.next().unwrap_or_default().to_string(),
+        path: request_line_parts.next().unwrap_or_default().to_string(),
+        body: String::from_utf8_lossy(&request_bytes[header_length..]).to_string(),
+    };
+    let (status, body) = respond(&request);
+    requests.lock().unwrap().push(request);
+
+    let response = format!(
+        "HTTP/1.1 {} Test\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -0,0 +1,334 @@
+use serde::{Deserialize, Serialize};
+
+/// What a unit measures. Prices can only be converted between units that measure
+/// the same thing.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+    Mass,
+    Volume,
+    Energy,
+    Count,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Unit {
+    Gram,
+    Kilogram,
+    /// Ounce by weight.
+    Ounce,
+    Pound,
+    Milliliter,
+    Liter,
+    FluidOunce,
+    Gallon,
+    KilowattHour,
+    Therm,
+    Each,
+    Dozen,
+}
+
+impl Unit {
+    fn get_dimension(self) -> Dimension {
+        match self {
+            Self::Gram | Self::Kilogram | Self::Ounce | Self::Pound => Dimension::Mass,
+            Self::Milliliter | Self::Liter | Self::FluidOunce | Self::Gallon => Dimension::Volume,
+            Self::KilowattHour | Self::Therm => Dimension::Energy,
+            Self::Each | Self::Dozen => Dimension::Count,
+        }
+    }
+
+    /// Size of one of this unit in grams, liters, kilowatt hours or items,
+    /// depending on its dimension. US units are the US customary definitions.
+    fn get_base_amount(self) -> f64 {
+        match self {
+            Self::Gram => 1.0,
+            Self::Kilogram => 1000.0,
+            Self::Ounce => 28.349_523_125,
+            Self::Pound => 453.592_37,
+            Self::Milliliter => 0.001,
+            Self::Liter => 1.0,
+            Self::FluidOunce => 0.029_573_529_562_5,
+            Self::Gallon => 3.785_411_784,
+            Self::KilowattHour => 1.0,
+            Self::Therm => 29.307_107_017_2,
+            Self::Each => 1.0,
+            Self::Dozen => 12.0,
+        }
+    }
+
+    /// The value accepted by the `unit` query parameter.
+    pub fn get_abbreviation(self) -> &'static str {
+        match self {
+            Self::Gram => "g",
+            Self::Kilogram => "kg",
+            Self::Ounce => "oz",
+            Self::Pound => "lb",
+            Self::Milliliter => "ml",
+            Self::Liter => "l",
+            Self::FluidOunce => "fl-oz",
+            Self::Gallon => "gal",
+            Self::KilowattHour => "kwh",
+            Self::Therm => "therm",
+            Self::Each => "each",
+            Self::Dozen => "dozen",
+        }
+    }
+
+    /// Matches the ways units are written in BLS item names. Ounces are assumed
+    /// to be by weight, since fluid ounces can't be told apart by name alone.
+    fn from_item_name_word(word: &str) -> Option<Self> {
+        match word.trim_end_matches('.') {
+            "g" | "gm" | "gram" | "grams" => Some(Self::Gram),
+            "kg" | "kilogram" | "kilograms" => Some(Self::Kilogram),
+            "oz" | "ounce" | "ounces" => Some(Self::Ounce),
+            "lb" | "lbs" | "pound" | "pounds" => Some(Self::Pound),
+            "ml" => Some(Self::Milliliter),
+            "l" | "lit" | "liter" | "liters" | "litre" | "litres" => Some(Self::Liter),
+            "gal" | "gallon" | "gallons" => Some(Self::Gallon),
+            "kwh" => Some(Self::KilowattHour),
+            "therm" | "therms" => Some(Self::Therm),
+            "doz" | "dozen" => Some(Self::Dozen),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> rocket::form::FromFormField<'a> for Unit {
+    fn from_value(field: rocket::form::ValueField<'a>) -> rocket::form::Result<'a, Self> {
+        match field.value {
+            "g" => Ok(Self::Gram),
+            "kg" => Ok(Self::Kilogram),
+            "oz" => Ok(Self::Ounce),
+            "lb" => Ok(Self::Pound),
+            "ml" => Ok(Self::Milliliter),
+            "l" => Ok(Self::Liter),
+            "fl-oz" => Ok(Self::FluidOunce),
+            "gal" => Ok(Self::Gallon),
+            "kwh" => Ok(Self::KilowattHour),
+            "therm" => Ok(Self::Therm),
+            "each" => Ok(Self::Each),
+            "dozen" => Ok(Self::Dozen),
+            _ => Err(
+                rocket::form::Error::validation(format!("Unknown unit: {}", field.value)).into(),
+            ),
+        }
+    }
+}
+
+/// How much of an item each of its average prices is for, e.g. 1 pound or
+/// 16 fluid ounces.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceUnit {
+    quantity: f64,
+    unit: Unit,
+}
+
+impl PriceUnit {
+    /// Extracts the priced quantity from a BLS item name. Names end with the
+    /// quantity in a few different formats, e.g. "per lb. (453.6 gm)", "(cost per
+    /// 16 ounces/473.2 ml)", "per 1/2 gal. (1.9 lit)" or "- 40 therms". Returns
+    /// `None` for names without a recognizable quantity, such as CPI-U items.
+    pub fn from_item_name(item_name: &str) -> Option<Self> {
+        let item_name = item_name.to_lowercase();
+        let priced_quantity_text = match item_name.rfind("per ") {
+            Some(index) => &item_name[index + "per ".len()..],
+            None => &item_name[item_name.rfind(" - ")? + " - ".len()..],
+        };
+
+        let measurements = parse_measurements(priced_quantity_text);
+        let (quantity, mut unit) = *measurements.first()?;
+
+        // Names that are priced in ounces give the metric equivalent, which
+        // tells us whether they're fluid ounces.
+        if unit == Unit::Ounce
+            && measurements
+                .iter()
+                .any(|(_, other_unit)| other_unit.get_dimension() == Dimension::Volume)
+        {
+            unit = Unit::FluidOunce;
+        }
+
+        if quantity <= 0.0 {
+            return None;
+        }
+        Some(Self { quantity, unit })
+    }
+
+    /// Returns the number to multiply a price for this quantity by to get the
+    /// price for one `target_unit`, or `None` if they measure different things.
+    pub fn get_conversion_factor(&self, target_unit: Unit) -> Option<f64> {
+        if self.unit.get_dimension() != target_unit.get_dimension() {
+            return None;
+        }
+        Some(target_unit.get_base_amount() / (self.quantity * self.unit.get_base_amount()))
+    }
+
+    pub fn get_unit(&self) -> Unit {
+        self.unit
+    }
+}
+
+/// Finds every `[<quantity>] <unit>` pair in `text`, in order. The quantity
+/// defaults to 1, and can be a decimal, a fraction like `1/2`, or `one-half`.
+fn parse_measurements(text: &str) -> Vec<(f64, Unit)> {
+    // Slashes separate fractions as well as alternative measurements, e.g.
+    // "gallon/3.785 liters", so they're kept as their own words.
+    let words: Vec<&str> = text
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '/'))
+        .flat_map(split_keeping_slashes)
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let mut measurements = Vec::new();
+    let mut quantity_or: Option<f64> = None;
+    let mut i = 0;
+    while i < words.len() {
+        let word = words[i];
+        if let Some(unit) = Unit::from_item_name_word(word) {
+            measurements.push((quantity_or.take().unwrap_or(1.0), unit));
+        } else if word == "one-half" {
+            quantity_or = Some(0.5);
+        } else if let Ok(number) = word.trim_end_matches('.').parse::<f64>() {
+            quantity_or = Some(number);
+            // Fractions, e.g. "1/2".
+            if let (Some(&"/"), Some(Ok(denominator))) = (
+                words.get(i + 1),
+                words.get(i + 2).map(|word| word.parse::<f64>()),
+            ) {
+                if denominator != 0.0 {
+                    quantity_or = Some(number / denominator);
+                }
+                i += 2;
+            }
+        } else {
+            quantity_or = None;
+        }
+        i += 1;
+    }
+
+    measurements
+}
+
+fn split_keeping_slashes(word: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut part_start = 0;
+    for (index, c) in word.char_indices() {
+        if c == '/' {
+            parts.push(&word[part_start..index]);
+            parts.push("/");
+            part_start = index + 1;
+        }
+    }
+    parts.push(&word[part_start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_price_unit(item_name: &str, quantity: f64, unit: Unit) {
+        let price_unit = PriceUnit::from_item_name(item_name).unwrap();
+        assert!(
+            price_unit.quantity == quantity && price_unit.unit == unit,
+            "{}: expected {} {}, got {} {}",
+            item_name,
+            quantity,
+            unit.get_abbreviation(),
+            price_unit.quantity,
+            price_unit.unit.get_abbreviation()
+        );
+    }
+
+    #[test]
+    fn parses_item_names() {
+        assert_price_unit(
+            "Flour, white, all purpose, per lb. (453.6 gm)",
+            1.0,
+            Unit::Pound,
+        );
+        assert_price_unit("Spaghetti (cost per pound/453.6 grams)", 1.0, Unit::Pound);
+        assert_price_unit(
+            "Milk, fresh, whole, fortified, per 1/2 gal. (1.9 lit)",
+            0.5,
+            Unit::Gallon,
+        );
+        assert_price_unit(
+            "Milk, fresh, skim (cost per one-half gallon/1.9 liters)",
+            0.5,
+            Unit::Gallon,
+        );
+        assert_price_unit(
+            "Gasoline, unleaded regular, per gallon/3.785 liters",
+            1.0,
+            Unit::Gallon,
+        );
+        assert_price_unit("Eggs, grade A, large, per doz.", 1.0, Unit::Dozen);
+        assert_price_unit("Cola, nondiet, per 2 liters (67.6 oz)", 2.0, Unit::Liter);
+        assert_price_unit("Utility (piped) gas - 40 therms", 40.0, Unit::Therm);
+        assert_price_unit("Electricity per 500 KWH", 500.0, Unit::KilowattHour);
+        assert!(PriceUnit::from_item_name("All items").is_none());
+        assert!(PriceUnit::from_item_name("Bananas, per 0 lb.").is_none());
+    }
+
+    #[test]
+    fn tells_fluid_ounces_apart_by_their_metric_equivalent() {
+        assert_price_unit(
+            "Orange juice, frozen concentrate, 12 oz. can, per 16 oz. (473.2 ml)",
+            16.0,
+            Unit::FluidOunce,
+        );
+        assert_price_unit(
+            "Cola, non diet, return bottles, 24-40 ounce (cost per 16 ounces/473.2 ml)",
+            16.0,
+            Unit::FluidOunce,
+        );
+        assert_price_unit(
+            "Coffee, freeze dried, regular, all sizes (cost per 16 ounces/453.6 grams)",
+            16.0,
+            Unit::Ounce,
+        );
+        assert_price_unit("Potato chips, per 16 oz.", 16.0, Unit::Ounce);
+    }
+
+    #[test]
+    fn converts_between_units_of_the_same_dimension() {
+        let get_conversion_factor = |item_name: &str, target_unit: Unit| {
+            PriceUnit::from_item_name(item_name)
+                .unwrap()
+                .get_conversion_factor(target_unit)
+        };
+        let assert_close = |value_or: Option<f64>, expected_value: f64| {
+            assert!((value_or.unwrap() - expected_value).abs() < 1e-9);
+        };
+
+        assert_close(
+            get_conversion_factor("Bacon, sliced, per lb. (453.6 gm)", Unit::Kilogram),
+            1000.0 / 453.592_37,
+        );
+        assert_close(
+            get_conversion_factor("Ice cream, per 1/2 gal. (1.9 lit)", Unit::Gallon),
+            2.0,
+        );
+        assert_close(
+            get_conversion_factor("Malt beverages, per 16 oz. (473.2 ml)", Unit::Liter),
+            1.0 / (16.0 * 0.029_573_529_562_5),
+        );
+        assert_close(
+            get_conversion_factor("Eggs, grade A, large, per doz.", Unit::Each),
+            1.0 / 12.0,
+        );
+        assert_close(
+            get_conversion_factor("Electricity per 500 KWH", Unit::Therm),
+            29.307_107_017_2 / 500.0,
+        );
+
+        assert!(get_conversion_factor("Bacon, sliced, per lb. (453.6 gm)", Unit::Liter).is_none());
+        assert!(
+            get_conversion_factor("Malt beverages, per 16 oz. (473.2 ml)", Unit::Ounce).is_none()
+        );
+        assert!(get_conversion_factor("Electricity per KWH", Unit::Each).is_none());
+    }
+}
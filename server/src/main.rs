@@ -2,8 +2,10 @@
 extern crate rocket;
 
 use chrono::Datelike;
-use chrono::{TimeZone, Utc};
+use chrono::{Date, TimeZone, Utc};
 use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome},
     response::{content, status},
     Request, State,
 };
@@ -11,6 +13,7 @@ use rocket::{
 mod bpi;
 
 use bpi::{AreaCode, ItemCode};
+use std::sync::Arc;
 
 const FAVICON_BYTES: &[u8] = include_bytes!("../../client/out/favicon.ico");
 const HTML_BYTES: &[u8] = include_bytes!("../../client/out/index.html");
@@ -74,90 +77,909 @@ fn not_found_handler(req: &Request) -> NotFoundResponse {
     }
 }
 
-#[get("/bpi/item?<item_code>&<area_code>&<start_year>&<start_month>&<end_year>&<end_month>")]
-fn bpi_item_handler(
-    item_code: ItemCode,
-    area_code: AreaCode,
+fn get_start_date_or(
     start_year: Option<i32>,
     start_month: Option<u32>,
-    end_year: Option<i32>,
-    end_month: Option<u32>,
-    bpi_engine: &State<bpi::BPIEngine>,
-) -> rocket::response::content::Json<String> {
-    let start_or = if start_year.is_some() || start_month.is_some() {
+) -> Result<Option<Date<Utc>>, status::Custom<String>> {
+    if start_year.is_some() || start_month.is_some() {
         let start_year = start_year.unwrap_or_else(|| chrono::Utc::now().date().year()); // Default to current year.
         let start_month = start_month.unwrap_or(1); // Default to January.
 
-        Some(Utc.ymd(start_year, start_month, 1))
+        get_month_start(start_year, start_month).map(Some)
     } else {
-        None
-    };
+        Ok(None)
+    }
+}
 
-    let end_or = if end_year.is_some() || end_month.is_some() {
+fn get_end_date_or(
+    end_year: Option<i32>,
+    end_month: Option<u32>,
+) -> Result<Option<Date<Utc>>, status::Custom<String>> {
+    if end_year.is_some() || end_month.is_some() {
         let end_year = end_year.unwrap_or_else(|| chrono::Utc::now().date().year()); // Default to current year.
         let end_month = end_month.unwrap_or(12); // Default to December.
 
-        Some(Utc.ymd(end_year, end_month, 1)) // TODO - Find a way to get last day of month instead of first.
+        // Ranges include the whole end month.
+        get_month_start(end_year, end_month)
+            .map(|month_start| Some(bpi::get_next_month_start(month_start).pred()))
     } else {
-        None
+        Ok(None)
+    }
+}
+
+/// Returns a 503 response if no CPI-U index data is loaded, so that endpoints built
+/// on it don't return empty results without explanation.
+fn check_index_data_loaded(bpi_engine: &bpi::BPIEngine) -> Result<(), status::Custom<String>> {
+    if bpi_engine.has_index_data() {
+        Ok(())
+    } else {
+        Err(status::Custom(
+            Status::ServiceUnavailable,
+            "No CPI-U data loaded. Add the BLS `cu` flat files (CU-AREAS.txt, CU-ITEMS.txt \
+             and CU-TIME-SERIES.txt) to the CPI data directory and reload."
+                .to_string(),
+        ))
+    }
+}
+
+/// Returns the first day of a month, or a 400 response if there's no such month.
+fn get_month_start(year: i32, month: u32) -> Result<Date<Utc>, status::Custom<String>> {
+    match Utc.ymd_opt(year, month, 1).single() {
+        Some(date) => Ok(date),
+        None => Err(status::Custom(
+            Status::BadRequest,
+            format!("Invalid month: {}-{:02}", year, month),
+        )),
+    }
+}
+
+#[get("/bpi/item?<item_code>&<area_code>&<start_year>&<start_month>&<end_year>&<end_month>&<interval>&<price_basis>&<interpolation>&<observed_only>&<smoothing>&<window>&<unit>")]
+#[allow(clippy::too_many_arguments)]
+fn bpi_item_handler(
+    item_code: ItemCode,
+    area_code: AreaCode,
+    start_year: Option<i32>,
+    start_month: Option<u32>,
+    end_year: Option<i32>,
+    end_month: Option<u32>,
+    interval: Option<bpi::InterpolationInterval>,
+    price_basis: Option<bpi::PriceBasis>,
+    interpolation: Option<bpi::InterpolationStrategy>,
+    observed_only: Option<bool>,
+    smoothing: Option<bpi::SmoothingMethod>,
+    window: Option<usize>,
+    unit: Option<bpi::Unit>,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+) -> Result<rocket::response::content::Json<String>, status::Custom<String>> {
+    match get_item_series_entries(
+        &bpi_engine.get(),
+        item_code,
+        area_code,
+        get_start_date_or(start_year, start_month)?,
+        get_end_date_or(end_year, end_month)?,
+        interval,
+        price_basis,
+        interpolation,
+        observed_only,
+        smoothing,
+        window,
+        unit,
+    ) {
+        Ok(series_entries) => Ok(rocket::response::content::Json(
+            serde_json::json!(series_entries.as_slice()).to_string(),
+        )),
+        Err(e) => Err(status::Custom(Status::BadRequest, e)),
+    }
+}
+
+/// Summarizes the same series that `/bpi/item` returns for these parameters.
+#[get("/bpi/item/statistics?<item_code>&<area_code>&<start_year>&<start_month>&<end_year>&<end_month>&<interval>&<price_basis>&<interpolation>&<observed_only>&<smoothing>&<window>&<unit>")]
+#[allow(clippy::too_many_arguments)]
+fn bpi_item_statistics_handler(
+    item_code: ItemCode,
+    area_code: AreaCode,
+    start_year: Option<i32>,
+    start_month: Option<u32>,
+    end_year: Option<i32>,
+    end_month: Option<u32>,
+    interval: Option<bpi::InterpolationInterval>,
+    price_basis: Option<bpi::PriceBasis>,
+    interpolation: Option<bpi::InterpolationStrategy>,
+    observed_only: Option<bool>,
+    smoothing: Option<bpi::SmoothingMethod>,
+    window: Option<usize>,
+    unit: Option<bpi::Unit>,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+) -> Result<rocket::response::content::Json<String>, status::Custom<String>> {
+    match get_item_series_entries(
+        &bpi_engine.get(),
+        item_code,
+        area_code,
+        get_start_date_or(start_year, start_month)?,
+        get_end_date_or(end_year, end_month)?,
+        interval,
+        price_basis,
+        interpolation,
+        observed_only,
+        smoothing,
+        window,
+        unit,
+    ) {
+        Ok(series_entries) => Ok(rocket::response::content::Json(
+            serde_json::json!(bpi::get_series_statistics(&series_entries)).to_string(),
+        )),
+        Err(e) => Err(status::Custom(Status::BadRequest, e)),
+    }
+}
+
+/// Prices an item in sats on a single day, showing the BTC price used and the
+/// known prices that both sides were interpolated from.
+#[get("/bpi/item/at?<item_code>&<area_code>&<year>&<month>&<day>&<price_basis>&<interpolation>")]
+#[allow(clippy::too_many_arguments)]
+fn bpi_item_at_handler(
+    item_code: ItemCode,
+    area_code: AreaCode,
+    year: i32,
+    month: u32,
+    day: u32,
+    price_basis: Option<bpi::PriceBasis>,
+    interpolation: Option<bpi::InterpolationStrategy>,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+) -> Result<rocket::response::content::Json<String>, status::Custom<String>> {
+    let date = match Utc.ymd_opt(year, month, day).single() {
+        Some(date) => date,
+        None => {
+            return Err(status::Custom(
+                Status::BadRequest,
+                format!("Invalid date: {}-{:02}-{:02}", year, month, day),
+            ))
+        }
     };
+    let not_found_message = format!(
+        "No data for item {} in area {} on {}",
+        item_code.as_str(),
+        area_code.as_str(),
+        date.format("%Y-%m-%d")
+    );
 
-    rocket::response::content::Json(
-        // TODO - Don't hardcode to `Daily` - set this based on a request parameter.
-        serde_json::json!(bpi_engine.get_series_data(
+    match bpi_engine.get().get_point_in_time_data(
+        item_code,
+        area_code,
+        date,
+        price_basis.unwrap_or(bpi::PriceBasis::Open), // Default to open.
+        interpolation.unwrap_or(bpi::InterpolationStrategy::Linear), // Default to linear.
+    ) {
+        Some(point_in_time_entry) => Ok(rocket::response::content::Json(
+            serde_json::json!(point_in_time_entry).to_string(),
+        )),
+        None => Err(status::Custom(Status::NotFound, not_found_message)),
+    }
+}
+
+/// Fills in defaults for the `/bpi/item` query parameters and fetches the series,
+/// converting it to `unit` and smoothing it afterwards if requested. Fails if the
+/// item can't be priced per `unit`.
+#[allow(clippy::too_many_arguments)]
+fn get_item_series_entries(
+    bpi_engine: &bpi::BPIEngine,
+    item_code: ItemCode,
+    area_code: AreaCode,
+    start_or: Option<Date<Utc>>,
+    end_or: Option<Date<Utc>>,
+    interval: Option<bpi::InterpolationInterval>,
+    price_basis: Option<bpi::PriceBasis>,
+    interpolation: Option<bpi::InterpolationStrategy>,
+    observed_only: Option<bool>,
+    smoothing: Option<bpi::SmoothingMethod>,
+    window: Option<usize>,
+    unit: Option<bpi::Unit>,
+) -> Result<Arc<Vec<bpi::BPISeriesEntry>>, String> {
+    let price_basis = price_basis.unwrap_or(bpi::PriceBasis::Open); // Default to open.
+
+    // Checked before fetching the series, since it's much cheaper.
+    let unit_conversion_factor_or = match unit {
+        Some(unit) => Some(bpi_engine.get_unit_conversion_factor(&item_code, unit)?),
+        None => None,
+    };
+
+    // Observed-only series have one entry per CPI data point, so `interval`
+    // and `interpolation` don't apply.
+    // Cached series are shared, so they're only copied if they need to be changed.
+    let mut series_entries = if observed_only.unwrap_or(false) {
+        Arc::from(bpi_engine.get_observed_series_data(
             item_code,
             area_code,
             start_or,
             end_or,
-            bpi::InterpolationInterval::Daily
+            price_basis,
+        ))
+    } else {
+        bpi_engine.get_series_data(
+            item_code,
+            area_code,
+            start_or,
+            end_or,
+            interval.unwrap_or(bpi::InterpolationInterval::Daily), // Default to daily.
+            price_basis,
+            interpolation.unwrap_or(bpi::InterpolationStrategy::Linear), // Default to linear.
+        )
+    };
+
+    if let Some(unit_conversion_factor) = unit_conversion_factor_or {
+        series_entries = Arc::from(
+            series_entries
+                .iter()
+                .map(|series_entry| series_entry.clone().scale(unit_conversion_factor))
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    Ok(match smoothing {
+        Some(smoothing) => Arc::from(bpi::smooth_series(
+            &series_entries,
+            smoothing,
+            window.unwrap_or(30), // Default to 30 entries, e.g. a month of daily data.
+        )),
+        None => series_entries,
+    })
+}
+
+/// Prices several series, passed as repeated `series=<item_code>:<area_code>`
+/// parameters, on a shared date axis.
+#[get("/bpi/compare?<series>&<start_year>&<start_month>&<end_year>&<end_month>&<interval>&<price_basis>&<interpolation>&<intersection_only>")]
+#[allow(clippy::too_many_arguments)]
+fn bpi_compare_handler(
+    series: Vec<bpi::SeriesKey>,
+    start_year: Option<i32>,
+    start_month: Option<u32>,
+    end_year: Option<i32>,
+    end_month: Option<u32>,
+    interval: Option<bpi::InterpolationInterval>,
+    price_basis: Option<bpi::PriceBasis>,
+    interpolation: Option<bpi::InterpolationStrategy>,
+    intersection_only: Option<bool>,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+) -> Result<rocket::response::content::Json<String>, status::Custom<String>> {
+    let bpi_engine = bpi_engine.get();
+
+    Ok(rocket::response::content::Json(
+        serde_json::json!(bpi_engine.get_comparison_series_data(
+            series,
+            get_start_date_or(start_year, start_month)?,
+            get_end_date_or(end_year, end_month)?,
+            interval.unwrap_or(bpi::InterpolationInterval::Daily), // Default to daily.
+            price_basis.unwrap_or(bpi::PriceBasis::Open),          // Default to open.
+            interpolation.unwrap_or(bpi::InterpolationStrategy::Linear), // Default to linear.
+            intersection_only.unwrap_or(false)
         ))
         .to_string(),
-    )
+    ))
+}
+
+/// Ranks an item's sats price across every CPI area. Pass `year` and `month` to
+/// compare a single month, or a start and end to compare averages over a range.
+#[get("/bpi/regions?<item_code>&<year>&<month>&<start_year>&<start_month>&<end_year>&<end_month>&<price_basis>&<interpolation>")]
+#[allow(clippy::too_many_arguments)]
+fn bpi_regions_handler(
+    item_code: ItemCode,
+    year: Option<i32>,
+    month: Option<u32>,
+    start_year: Option<i32>,
+    start_month: Option<u32>,
+    end_year: Option<i32>,
+    end_month: Option<u32>,
+    price_basis: Option<bpi::PriceBasis>,
+    interpolation: Option<bpi::InterpolationStrategy>,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+) -> Result<rocket::response::content::Json<String>, status::Custom<String>> {
+    let bpi_engine = bpi_engine.get();
+
+    let (start_or, end_or) = match get_start_date_or(year, month)? {
+        Some(date) => (Some(date), Some(date)),
+        None => (
+            get_start_date_or(start_year, start_month)?,
+            get_end_date_or(end_year, end_month)?,
+        ),
+    };
+
+    Ok(rocket::response::content::Json(
+        serde_json::json!(bpi_engine.get_regional_comparison_data(
+            item_code,
+            start_or,
+            end_or,
+            price_basis.unwrap_or(bpi::PriceBasis::Open), // Default to open.
+            interpolation.unwrap_or(bpi::InterpolationStrategy::Linear)  // Default to linear.
+        ))
+        .to_string(),
+    ))
+}
+
+/// Prices a basket of items, passed as repeated `item=<item_code>:<quantity>`
+/// parameters, in sats over time.
+#[get("/bpi/basket?<item>&<area_code>&<start_year>&<start_month>&<end_year>&<end_month>&<interval>&<price_basis>&<interpolation>")]
+#[allow(clippy::too_many_arguments)]
+fn bpi_basket_handler(
+    item: Vec<bpi::BasketItem>,
+    area_code: AreaCode,
+    start_year: Option<i32>,
+    start_month: Option<u32>,
+    end_year: Option<i32>,
+    end_month: Option<u32>,
+    interval: Option<bpi::InterpolationInterval>,
+    price_basis: Option<bpi::PriceBasis>,
+    interpolation: Option<bpi::InterpolationStrategy>,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+) -> Result<rocket::response::content::Json<String>, status::Custom<String>> {
+    let bpi_engine = bpi_engine.get();
+
+    Ok(rocket::response::content::Json(
+        serde_json::json!(bpi_engine.get_basket_series_data(
+            &item,
+            area_code,
+            get_start_date_or(start_year, start_month)?,
+            get_end_date_or(end_year, end_month)?,
+            interval.unwrap_or(bpi::InterpolationInterval::Daily), // Default to daily.
+            price_basis.unwrap_or(bpi::PriceBasis::Open),          // Default to open.
+            interpolation.unwrap_or(bpi::InterpolationStrategy::Linear)  // Default to linear.
+        ))
+        .to_string(),
+    ))
+}
+
+#[get("/bpi/index?<item_code>&<area_code>&<base_year>&<base_month>&<start_year>&<start_month>&<end_year>&<end_month>&<interval>&<price_basis>&<interpolation>")]
+#[allow(clippy::too_many_arguments)]
+fn bpi_index_handler(
+    item_code: ItemCode,
+    area_code: AreaCode,
+    base_year: Option<i32>,
+    base_month: Option<u32>,
+    start_year: Option<i32>,
+    start_month: Option<u32>,
+    end_year: Option<i32>,
+    end_month: Option<u32>,
+    interval: Option<bpi::InterpolationInterval>,
+    price_basis: Option<bpi::PriceBasis>,
+    interpolation: Option<bpi::InterpolationStrategy>,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+) -> Result<rocket::response::content::Json<String>, status::Custom<String>> {
+    let bpi_engine = bpi_engine.get();
+    check_index_data_loaded(&bpi_engine)?;
+    let not_found_message = format!(
+        "No CPI-U series for item {} in area {}",
+        item_code.as_str(),
+        area_code.as_str()
+    );
+
+    match bpi_engine.get_index_series_data(
+        item_code,
+        area_code,
+        get_start_date_or(base_year, base_month)?,
+        get_start_date_or(start_year, start_month)?,
+        get_end_date_or(end_year, end_month)?,
+        interval.unwrap_or(bpi::InterpolationInterval::Daily), // Default to daily.
+        price_basis.unwrap_or(bpi::PriceBasis::Open),          // Default to open.
+        interpolation.unwrap_or(bpi::InterpolationStrategy::Linear), // Default to linear.
+    ) {
+        Some(index_entries) => Ok(rocket::response::content::Json(
+            serde_json::json!(index_entries).to_string(),
+        )),
+        None => Err(status::Custom(Status::NotFound, not_found_message)),
+    }
+}
+
+/// Composite index of every average price item, weighted by the BLS relative
+/// importance weights in the CPI data directory.
+#[get("/bpi/bitcoin-cpi?<area_code>&<base_year>&<base_month>&<start_year>&<start_month>&<end_year>&<end_month>&<interval>&<price_basis>&<interpolation>")]
+#[allow(clippy::too_many_arguments)]
+fn bpi_bitcoin_cpi_handler(
+    area_code: AreaCode,
+    base_year: Option<i32>,
+    base_month: Option<u32>,
+    start_year: Option<i32>,
+    start_month: Option<u32>,
+    end_year: Option<i32>,
+    end_month: Option<u32>,
+    interval: Option<bpi::InterpolationInterval>,
+    price_basis: Option<bpi::PriceBasis>,
+    interpolation: Option<bpi::InterpolationStrategy>,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+) -> Result<rocket::response::content::Json<String>, status::Custom<String>> {
+    let bpi_engine = bpi_engine.get();
+
+    Ok(rocket::response::content::Json(
+        serde_json::json!(bpi_engine.get_weighted_index_series_data(
+            area_code,
+            get_start_date_or(base_year, base_month)?,
+            get_start_date_or(start_year, start_month)?,
+            get_end_date_or(end_year, end_month)?,
+            interval.unwrap_or(bpi::InterpolationInterval::Daily), // Default to daily.
+            price_basis.unwrap_or(bpi::PriceBasis::Open),          // Default to open.
+            interpolation.unwrap_or(bpi::InterpolationStrategy::Linear)  // Default to linear.
+        ))
+        .to_string(),
+    ))
+}
+
+#[get("/bpi/expression?<expression>&<start_year>&<start_month>&<end_year>&<end_month>&<interval>&<price_basis>&<interpolation>")]
+#[allow(clippy::too_many_arguments)]
+fn bpi_expression_handler(
+    expression: String,
+    start_year: Option<i32>,
+    start_month: Option<u32>,
+    end_year: Option<i32>,
+    end_month: Option<u32>,
+    interval: Option<bpi::InterpolationInterval>,
+    price_basis: Option<bpi::PriceBasis>,
+    interpolation: Option<bpi::InterpolationStrategy>,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+) -> Result<rocket::response::content::Json<String>, status::Custom<String>> {
+    let bpi_engine = bpi_engine.get();
+
+    match bpi_engine.get_expression_series_data(
+        &expression,
+        get_start_date_or(start_year, start_month)?,
+        get_end_date_or(end_year, end_month)?,
+        interval.unwrap_or(bpi::InterpolationInterval::Daily), // Default to daily.
+        price_basis.unwrap_or(bpi::PriceBasis::Open),          // Default to open.
+        interpolation.unwrap_or(bpi::InterpolationStrategy::Linear), // Default to linear.
+    ) {
+        Ok(expression_entries) => Ok(rocket::response::content::Json(
+            serde_json::json!(expression_entries).to_string(),
+        )),
+        Err(e) => Err(status::Custom(Status::BadRequest, e)),
+    }
+}
+
+/// BTC price deflated by the CPI-U, in constant dollars of the base month.
+/// Defaults to headline inflation for the whole US, in the latest month's dollars.
+#[get("/bpi/real-btc-price?<item_code>&<area_code>&<base_year>&<base_month>&<start_year>&<start_month>&<end_year>&<end_month>&<interval>&<price_basis>&<interpolation>")]
+#[allow(clippy::too_many_arguments)]
+fn bpi_real_btc_price_handler(
+    item_code: Option<ItemCode>,
+    area_code: Option<AreaCode>,
+    base_year: Option<i32>,
+    base_month: Option<u32>,
+    start_year: Option<i32>,
+    start_month: Option<u32>,
+    end_year: Option<i32>,
+    end_month: Option<u32>,
+    interval: Option<bpi::InterpolationInterval>,
+    price_basis: Option<bpi::PriceBasis>,
+    interpolation: Option<bpi::InterpolationStrategy>,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+) -> Result<rocket::response::content::Json<String>, status::Custom<String>> {
+    let bpi_engine = bpi_engine.get();
+    check_index_data_loaded(&bpi_engine)?;
+    let item_code = item_code.unwrap_or_else(ItemCode::all_items); // Default to all items.
+    let area_code = area_code.unwrap_or_else(AreaCode::us_city_average); // Default to the whole US.
+    let not_found_message = format!(
+        "No CPI-U series for item {} in area {}",
+        item_code.as_str(),
+        area_code.as_str()
+    );
+
+    match bpi_engine.get_real_btc_price_series_data(
+        item_code,
+        area_code,
+        get_start_date_or(base_year, base_month)?,
+        get_start_date_or(start_year, start_month)?,
+        get_end_date_or(end_year, end_month)?,
+        interval.unwrap_or(bpi::InterpolationInterval::Daily), // Default to daily.
+        price_basis.unwrap_or(bpi::PriceBasis::Open),          // Default to open.
+        interpolation.unwrap_or(bpi::InterpolationStrategy::Linear), // Default to linear.
+    ) {
+        Some(real_price_entries) => Ok(rocket::response::content::Json(
+            serde_json::json!(real_price_entries).to_string(),
+        )),
+        None => Err(status::Custom(Status::NotFound, not_found_message)),
+    }
+}
+
+#[get("/bpi/categories")]
+fn bpi_categories_handler(
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+) -> rocket::response::content::Json<String> {
+    let bpi_engine = bpi_engine.get();
+    rocket::response::content::Json(serde_json::json!(bpi_engine.get_item_categories()).to_string())
+}
+
+/// Equal-weighted index of every item in a category, in the same format as
+/// `/bpi/bitcoin-cpi`.
+#[get("/bpi/categories/index?<category_code>&<area_code>&<base_year>&<base_month>&<start_year>&<start_month>&<end_year>&<end_month>&<interval>&<price_basis>&<interpolation>")]
+#[allow(clippy::too_many_arguments)]
+fn bpi_category_index_handler(
+    category_code: String,
+    area_code: AreaCode,
+    base_year: Option<i32>,
+    base_month: Option<u32>,
+    start_year: Option<i32>,
+    start_month: Option<u32>,
+    end_year: Option<i32>,
+    end_month: Option<u32>,
+    interval: Option<bpi::InterpolationInterval>,
+    price_basis: Option<bpi::PriceBasis>,
+    interpolation: Option<bpi::InterpolationStrategy>,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+) -> Result<rocket::response::content::Json<String>, status::Custom<String>> {
+    let bpi_engine = bpi_engine.get();
+
+    match bpi_engine.get_category_index_series_data(
+        &category_code,
+        area_code,
+        get_start_date_or(base_year, base_month)?,
+        get_start_date_or(start_year, start_month)?,
+        get_end_date_or(end_year, end_month)?,
+        interval.unwrap_or(bpi::InterpolationInterval::Daily), // Default to daily.
+        price_basis.unwrap_or(bpi::PriceBasis::Open),          // Default to open.
+        interpolation.unwrap_or(bpi::InterpolationStrategy::Linear), // Default to linear.
+    ) {
+        Some(index_entries) => Ok(rocket::response::content::Json(
+            serde_json::json!(index_entries).to_string(),
+        )),
+        None => Err(status::Custom(
+            Status::NotFound,
+            format!("Unknown category code: {}", category_code),
+        )),
+    }
+}
+
+#[get("/bpi/index/areas")]
+fn bpi_index_areas_handler(
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+) -> rocket::response::content::Json<String> {
+    let bpi_engine = bpi_engine.get();
+    rocket::response::content::Json(serde_json::json!(bpi_engine.get_index_areas()).to_string())
+}
+
+#[get("/bpi/index/items")]
+fn bpi_index_items_handler(
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+) -> rocket::response::content::Json<String> {
+    let bpi_engine = bpi_engine.get();
+    rocket::response::content::Json(serde_json::json!(bpi_engine.get_index_items()).to_string())
 }
 
 #[get("/bpi/datasets")]
 fn bpi_datasets_handler(
-    bpi_engine: &State<bpi::BPIEngine>,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
 ) -> rocket::response::content::Json<String> {
+    let bpi_engine = bpi_engine.get();
     rocket::response::content::Json(
         serde_json::json!(bpi_engine.get_valid_series_ranges()).to_string(),
     )
 }
 
+#[get("/bpi/search?<query>&<limit>")]
+fn bpi_search_handler(
+    query: String,
+    limit: Option<usize>,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+) -> rocket::response::content::Json<String> {
+    let bpi_engine = bpi_engine.get();
+    let limit = limit.unwrap_or(25); // Default to 25 results. Searches cap this at 100.
+    rocket::response::content::Json(serde_json::json!(bpi_engine.search(&query, limit)).to_string())
+}
+
 #[get("/bpi/areas")]
 fn bpi_areas_handler(
-    bpi_engine: &State<bpi::BPIEngine>,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
 ) -> rocket::response::content::Json<String> {
+    let bpi_engine = bpi_engine.get();
     rocket::response::content::Json(serde_json::json!(bpi_engine.get_areas()).to_string())
 }
 
 #[get("/bpi/items")]
 fn bpi_items_handler(
-    bpi_engine: &State<bpi::BPIEngine>,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
 ) -> rocket::response::content::Json<String> {
+    let bpi_engine = bpi_engine.get();
     rocket::response::content::Json(serde_json::json!(bpi_engine.get_items()).to_string())
 }
 
+/// Request guard for admin-only routes. Requests must send the token from the
+/// `SATDASH_ADMIN_TOKEN` environment variable as `Authorization: Bearer <token>`.
+/// If the environment variable isn't set, admin routes are disabled entirely.
+struct AdminAuth;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminAuth {
+    type Error = &'static str;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let admin_token = match std::env::var("SATDASH_ADMIN_TOKEN") {
+            Ok(admin_token) if !admin_token.is_empty() => admin_token,
+            _ => return Outcome::Failure((Status::Forbidden, "Admin routes are disabled")),
+        };
+
+        let token_or = req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|authorization| authorization.strip_prefix("Bearer "));
+        match token_or {
+            Some(token) if tokens_match(token, &admin_token) => Outcome::Success(AdminAuth),
+            _ => Outcome::Failure((Status::Unauthorized, "Invalid admin token")),
+        }
+    }
+}
+
+/// Compares tokens in constant time, so response times don't reveal how much of a
+/// guessed token is right. Only the length can leak.
+fn tokens_match(token: &str, expected_token: &str) -> bool {
+    token.len() == expected_token.len()
+        && token
+            .bytes()
+            .zip(expected_token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[post("/admin/reload-cpi")]
+async fn admin_reload_cpi_handler(
+    _admin_auth: AdminAuth,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+) -> Result<rocket::response::content::Json<String>, status::Custom<String>> {
+    println!("Reloading CPI data...");
+    if let Err(e) = bpi_engine.reload_cpi_data().await {
+        eprintln!("CPI data reload failed: {}", e);
+        return Err(status::Custom(
+            Status::InternalServerError,
+            format!("Failed to reload CPI data: {}", e),
+        ));
+    }
+    let bpi_engine = bpi_engine.get();
+    println!(
+        "CPI data reload complete! Found {} items across {} areas.",
+        bpi_engine.get_items().len(),
+        bpi_engine.get_areas().len()
+    );
+    Ok(rocket::response::content::Json(
+        serde_json::json!({
+            "items": bpi_engine.get_items().len(),
+            "areas": bpi_engine.get_areas().len(),
+        })
+        .to_string(),
+    ))
+}
+
+#[post("/admin/fetch-bls-series?<series_id>&<start_year>&<end_year>")]
+async fn admin_fetch_bls_series_handler(
+    _admin_auth: AdminAuth,
+    series_id: Vec<String>,
+    start_year: Option<i32>,
+    end_year: Option<i32>,
+    bpi_engine: &State<bpi::SharedBPIEngine>,
+    bls_api_client: &State<bpi::BlsApiClient>,
+) -> Result<rocket::response::content::Json<String>, status::Custom<String>> {
+    let end_year = end_year.unwrap_or_else(|| chrono::Utc::now().date().year()); // Default to current year.
+    let start_year = start_year.unwrap_or(end_year); // Default to only fetching one year.
+
+    match bpi_engine
+        .fetch_bls_series(bls_api_client, &series_id, start_year, end_year)
+        .await
+    {
+        Ok(value_count) => Ok(rocket::response::content::Json(
+            serde_json::json!({ "values": value_count }).to_string(),
+        )),
+        Err(e) => Err(status::Custom(Status::BadRequest, e.to_string())),
+    }
+}
+
 #[rocket::launch]
-fn rocket() -> _ {
+async fn rocket() -> _ {
     println!("Building BPI index...");
-    let bpi_engine = bpi::BPIEngine::new();
+    // If unset, the CPI flat files embedded in the binary are used.
+    let cpi_data_dir_or = std::env::var_os("SATDASH_CPI_DATA_DIR").map(std::path::PathBuf::from);
+    // If set, the parsed index is loaded from this file when it's up to date,
+    // and written to it otherwise.
+    let snapshot_path_or = std::env::var_os("SATDASH_SNAPSHOT_FILE").map(std::path::PathBuf::from);
+    let snapshot_engine_or = match &snapshot_path_or {
+        Some(snapshot_path) => {
+            bpi::BPIEngine::load_snapshot_or(cpi_data_dir_or.clone(), snapshot_path)
+        }
+        None => None,
+    };
+    let loaded_from_snapshot = snapshot_engine_or.is_some();
+    let bpi_engine = match snapshot_engine_or {
+        Some(bpi_engine) => bpi_engine,
+        None => {
+            let bpi_engine = bpi::BPIEngine::new(cpi_data_dir_or.clone()).await;
+            if let Some(snapshot_path) = &snapshot_path_or {
+                match bpi_engine.write_snapshot(cpi_data_dir_or.as_deref(), snapshot_path) {
+                    Ok(()) => println!("Wrote BPI index snapshot to {}.", snapshot_path.display()),
+                    Err(e) => eprintln!(
+                        "Failed to write BPI index snapshot to {}: {}",
+                        snapshot_path.display(),
+                        e
+                    ),
+                }
+            }
+            bpi_engine
+        }
+    };
     println!(
         "BPI index complete! Found {} items across {} areas.",
         bpi_engine.get_items().len(),
         bpi_engine.get_areas().len()
     );
+    let bpi_engine = bpi::SharedBPIEngine::new(bpi_engine);
+
+    // BTC prices in the snapshot may be out of date, so fetch fresh ones right away
+    // rather than waiting for the first periodic refresh.
+    if loaded_from_snapshot {
+        let bpi_engine = bpi_engine.clone();
+        rocket::tokio::spawn(async move { bpi_engine.refresh_btc_prices().await });
+    }
+
+    // Set `SATDASH_BTC_PRICE_REFRESH_SECS` to 0 to disable background refreshes.
+    let btc_price_refresh_secs = std::env::var("SATDASH_BTC_PRICE_REFRESH_SECS")
+        .ok()
+        .and_then(|secs| secs.parse::<u64>().ok())
+        .unwrap_or(6 * 60 * 60); // Default to every 6 hours.
+    if btc_price_refresh_secs > 0 {
+        rocket::tokio::spawn(bpi_engine.clone().refresh_btc_prices_periodically(
+            std::time::Duration::from_secs(btc_price_refresh_secs),
+        ));
+    }
+
     println!("Starting server...");
+    build_rocket(bpi_engine)
+}
+
+fn build_rocket(bpi_engine: bpi::SharedBPIEngine) -> rocket::Rocket<rocket::Build> {
     rocket::build()
         .manage(bpi_engine)
+        .manage(bpi::BlsApiClient::from_env())
         .register("/", catchers![not_found_handler])
         .mount(
             "/api",
             routes![
                 bpi_item_handler,
+                bpi_item_statistics_handler,
+                bpi_item_at_handler,
+                bpi_basket_handler,
+                bpi_compare_handler,
+                bpi_regions_handler,
                 bpi_datasets_handler,
+                bpi_search_handler,
                 bpi_areas_handler,
-                bpi_items_handler
+                bpi_items_handler,
+                bpi_index_handler,
+                bpi_expression_handler,
+                bpi_real_btc_price_handler,
+                bpi_index_areas_handler,
+                bpi_index_items_handler,
+                bpi_bitcoin_cpi_handler,
+                bpi_categories_handler,
+                bpi_category_index_handler,
+                admin_reload_cpi_handler,
+                admin_fetch_bls_series_handler
             ],
         )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::blocking::Client;
+
+    /// Serves one flour price per month from January 2022 to March 2023, a
+    /// constant BTC price every day over the same months, and `index_values`
+    /// as the CPI-U data.
+    fn get_test_client(index_values: &[(&str, &str, i32, u32, f64)]) -> Client {
+        let average_prices: Vec<(&str, &str, i32, u32, f64)> = (0..15)
+            .map(|i| ("0000", "701111", 2022 + i / 12, i as u32 % 12 + 1, 0.5))
+            .collect();
+        let btc_prices: Vec<(Date<Utc>, f64)> = (0..455)
+            .map(|day| (Utc.ymd(2022, 1, 1) + chrono::Duration::days(day), 20000.0))
+            .collect();
+        let bpi_engine = bpi::BPIEngine::from_test_data(&average_prices, index_values, &btc_prices);
+        Client::tracked(build_rocket(bpi::SharedBPIEngine::new(bpi_engine))).unwrap()
+    }
+
+    fn get_last_date(client: &Client, uri: &str) -> (i64, i64, i64) {
+        let response = client.get(uri).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let entries: serde_json::Value =
+            serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        let last_entry = entries.as_array().unwrap().last().unwrap();
+        (
+            last_entry["year"].as_i64().unwrap(),
+            last_entry["month"].as_i64().unwrap(),
+            last_entry["day"].as_i64().unwrap(),
+        )
+    }
+
+    #[test]
+    fn includes_the_whole_end_month() {
+        let client = get_test_client(&[]);
+
+        // Each interval's last date is the last one it reaches in February 2023.
+        for (interval, last_date) in [
+            ("daily", (2023, 2, 28)),
+            ("weekly", (2023, 2, 25)),
+            ("monthly", (2023, 2, 1)),
+            ("yearly", (2023, 1, 1)),
+        ] {
+            assert_eq!(
+                get_last_date(
+                    &client,
+                    &format!(
+                        "/api/bpi/item?item_code=701111&area_code=0000&start_year=2022\
+                         &start_month=1&end_year=2023&end_month=2&interval={}",
+                        interval
+                    )
+                ),
+                last_date,
+                "{}",
+                interval
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_end_months() {
+        let client = get_test_client(&[]);
+        let response = client
+            .get("/api/bpi/item?item_code=701111&area_code=0000&end_year=2023&end_month=13")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(response.into_string().unwrap(), "Invalid month: 2023-13");
+    }
+
+    #[test]
+    fn ignores_intervals_for_observed_only_series() {
+        let client = get_test_client(&[]);
+        let response = client
+            .get(
+                "/api/bpi/item?item_code=701111&area_code=0000&start_year=2022&start_month=3\
+                 &end_year=2022&end_month=5&interval=daily&observed_only=true",
+            )
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let entries: serde_json::Value =
+            serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        let dates_and_observed: Vec<(i64, i64, bool)> = entries
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| {
+                (
+                    entry["month"].as_i64().unwrap(),
+                    entry["day"].as_i64().unwrap(),
+                    entry["observed"].as_bool().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            dates_and_observed,
+            vec![(3, 1, true), (4, 1, true), (5, 1, true)]
+        );
+    }
+
+    #[test]
+    fn distinguishes_missing_cpi_u_series_from_missing_cpi_u_data() {
+        let client = get_test_client(&[]);
+        let response = client.get("/api/bpi/real-btc-price").dispatch();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+
+        let client = get_test_client(&[("0000", "SA0", 2022, 1, 100.0)]);
+        let response = client.get("/api/bpi/real-btc-price").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client
+            .get("/api/bpi/real-btc-price?item_code=SEFJ&area_code=0000")
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(
+            response.into_string().unwrap(),
+            "No CPI-U series for item SEFJ in area 0000"
+        );
+    }
+
+    #[test]
+    fn matches_only_identical_tokens() {
+        assert!(tokens_match("s3cret", "s3cret"));
+        assert!(!tokens_match("s3creT", "s3cret"));
+        assert!(!tokens_match("s3cre", "s3cret"));
+        assert!(!tokens_match("", "s3cret"));
+    }
+}